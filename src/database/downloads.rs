@@ -0,0 +1,95 @@
+use super::error::Error;
+use super::podcasts::EpisodeKey;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const TREE: &str = "download_queue_0.1";
+
+/// a download that has not finished yet
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedDownload {
+    pub url: String,
+    /// path of the partial download (ends in .part)
+    pub path: PathBuf,
+    pub added: DateTime<Utc>,
+    pub paused: bool,
+}
+
+/// downloads waiting or in progress, kept so they survive a restart
+#[derive(Clone)]
+pub struct DownloadQueue {
+    tree: sled::Tree,
+}
+
+impl DownloadQueue {
+    pub fn open(db: &sled::Db) -> sled::Result<Self> {
+        let tree = db.open_tree(TREE)?;
+        Ok(Self { tree })
+    }
+    pub fn add(&self, key: EpisodeKey, download: &QueuedDownload) -> Result<(), Error> {
+        let bytes = bincode::serialize(download).unwrap();
+        self.tree.insert(key, bytes)?;
+        Ok(())
+    }
+    pub fn remove(&self, key: EpisodeKey) -> Result<(), Error> {
+        self.tree.remove(key)?;
+        Ok(())
+    }
+    pub fn set_paused(&self, key: EpisodeKey, paused: bool) -> Result<(), Error> {
+        self.tree.fetch_and_update(key, |old| {
+            let mut download: QueuedDownload = bincode::deserialize(old?).unwrap();
+            download.paused = paused;
+            Some(bincode::serialize(&download).unwrap())
+        })?;
+        Ok(())
+    }
+    /// the queued downloads in the order they were added
+    pub fn get_all(&self) -> Result<Vec<(EpisodeKey, QueuedDownload)>, Error> {
+        let mut queued = Vec::new();
+        for res in self.tree.iter() {
+            let (key, bytes) = res?;
+            let download: QueuedDownload = bincode::deserialize(&bytes).unwrap();
+            queued.push((EpisodeKey::from(key), download));
+        }
+        queued.sort_by_key(|(_, d)| d.added);
+        Ok(queued)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn queue_keeps_order_added() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let queue = DownloadQueue::open(&db).unwrap();
+        let now = Utc::now();
+        for i in 0..3u8 {
+            let download = QueuedDownload {
+                url: format!("https://example.org/{}.mp3", i),
+                path: PathBuf::from(format!("{}.mp3.part", i)),
+                added: now + Duration::seconds(i.into()),
+                paused: false,
+            };
+            // keys sort in the reverse order of adding
+            queue
+                .add(EpisodeKey::from(&[9 - i; 16][..]), &download)
+                .unwrap();
+        }
+        queue.remove(EpisodeKey::from(&[8; 16][..])).unwrap();
+
+        let urls: Vec<_> = queue
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .map(|(_, d)| d.url)
+            .collect();
+        assert_eq!(
+            urls,
+            ["https://example.org/0.mp3", "https://example.org/2.mp3"]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use super::error::Error;
+use super::podcasts::{EpisodeKey, PodcastDb, PodcastKey};
+use super::types::{EpisodeExt, Podcast};
+use crate::download::{move_downloads, move_legacy_dir};
+use std::collections::HashMap;
+
+/// layout of the database before episodes were keyed on their guid and
+/// podcasts on their url
+mod v0_1 {
+    use super::super::types::Date;
+    use serde::{Deserialize, Serialize};
+
+    pub const BASIC: &str = "podcasts_b_0.1";
+    pub const EXTENDED: &str = "podcasts_e_0.1";
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct EpisodeExt {
+        pub stream_url: String,
+        pub duration: f32,
+        pub title: String,
+        pub podcast: String,
+        pub date: Date,
+    }
+}
+
+impl From<v0_1::EpisodeExt> for EpisodeExt {
+    fn from(old: v0_1::EpisodeExt) -> Self {
+        Self {
+            guid: None,
+            stream_url: old.stream_url,
+            mime_type: None,
+            duration: old.duration,
+            title: old.title,
+            podcast: old.podcast,
+            date: old.date,
+            chapters_url: None,
+            chapters: Vec::new(),
+            chapters_fetched: false,
+        }
+    }
+}
+
+/// The old keys hashed titles using `DefaultHasher`. Podcasts are re-keyed
+/// on their url using the stable hash, episodes on their stream url as the
+/// guid is unknown. `PodcastDb::update_episodes` moves them to their guid
+/// based key and fills in the media type and chapters once the feed is
+/// refreshed. There are no cache headers yet so that refresh fetches
+/// every feed. The old trees are only dropped after everything is copied,
+/// an interrupted migration is simply run again.
+fn from_v0_1(db: &sled::Db) -> Result<(), Error> {
+    log::info!("migrating database from v0.1");
+    let old_basic = db.open_tree(v0_1::BASIC)?;
+    let old_extended = db.open_tree(v0_1::EXTENDED)?;
+    let new = PodcastDb::open(db)?;
+
+    let mut podcast_keys = HashMap::new();
+    for res in old_basic.iter() {
+        let (key, value) = res?;
+        if key.len() != 8 {
+            continue; // not a podcast but an episode
+        }
+        let podcast: Podcast = bincode::deserialize(&value).unwrap();
+        let new_key = PodcastKey::from_url(&podcast.url);
+        new.basic.insert(new_key, value)?;
+        podcast_keys.insert(key.to_vec(), new_key);
+        move_legacy_dir(&podcast.title, new_key);
+    }
+
+    for res in old_extended.iter() {
+        let (key, value) = res?;
+        let podcast_key = match podcast_keys.get(&key[..8]) {
+            Some(podcast_key) => *podcast_key,
+            None => continue, // episode of a podcast that no longer exists
+        };
+        let old: v0_1::EpisodeExt = bincode::deserialize(&value).unwrap();
+        let episode = EpisodeExt::from(old);
+        let new_key = EpisodeKey::from_episode(podcast_key, &episode);
+
+        // the layout of the basic episode info did not change, this
+        // keeps the progress
+        if let Some(basic) = old_basic.get(&key)? {
+            new.basic.insert(new_key, basic)?;
+        }
+        let extended = bincode::serialize(&episode).unwrap();
+        new.extended.insert(new_key, extended)?;
+    }
+
+    db.drop_tree(v0_1::BASIC)?;
+    db.drop_tree(v0_1::EXTENDED)?;
+    Ok(())
+}
+
+fn has_tree(db: &sled::Db, name: &str) -> bool {
+    db.tree_names()
+        .iter()
+        .any(|n| n.as_ref() == name.as_bytes())
+}
+
+/// downloads were named after the episode title before they were
+/// named after the episode key
+fn rename_downloads(db: &sled::Db) -> Result<(), Error> {
+    let pod_db = PodcastDb::open(db)?;
+    for (podcast_id, _) in pod_db.get_podcasts()? {
+        for (key, episode) in pod_db.get_episodes(podcast_id)? {
+            move_downloads(&episode.title, key);
+        }
+    }
+    Ok(())
+}
+
+pub fn run(db: &sled::Db) -> Result<(), Error> {
+    if has_tree(db, v0_1::BASIC) {
+        from_v0_1(db)?;
+        rename_downloads(db)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{Date, Episode, Progress};
+
+    #[test]
+    fn v0_1_keeps_progress() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let old_basic = db.open_tree(v0_1::BASIC).unwrap();
+        let old_extended = db.open_tree(v0_1::EXTENDED).unwrap();
+
+        let podcast = Podcast {
+            title: "Example".to_owned(),
+            url: "https://example.org/feed".to_owned(),
+        };
+        let old_podcast_key = [1u8; 8];
+        let mut old_episode_key = [1u8; 16];
+        old_episode_key[8..].copy_from_slice(&[2u8; 8]);
+        let episode = v0_1::EpisodeExt {
+            stream_url: "https://example.org/1.mp3".to_owned(),
+            duration: 60.,
+            title: "Episode 1".to_owned(),
+            podcast: podcast.title.clone(),
+            date: Date::Added(chrono::Utc::now()),
+        };
+        let basic = Episode {
+            title: episode.title.clone(),
+            duration: episode.duration,
+            progress: Progress::Listening(42.),
+            date: episode.date,
+        };
+        let podcast_bytes = bincode::serialize(&podcast).unwrap();
+        old_basic.insert(old_podcast_key, podcast_bytes).unwrap();
+        let basic_bytes = bincode::serialize(&basic).unwrap();
+        old_basic.insert(old_episode_key, basic_bytes).unwrap();
+        let episode_bytes = bincode::serialize(&episode).unwrap();
+        old_extended.insert(old_episode_key, episode_bytes).unwrap();
+
+        run(&db).unwrap();
+
+        let pod_db = PodcastDb::open(&db).unwrap();
+        let podcasts = pod_db.get_podcasts().unwrap();
+        assert_eq!(podcasts.len(), 1);
+        let (podcast_key, migrated) = &podcasts[0];
+        assert_eq!(migrated.url, podcast.url);
+        let episodes = pod_db.get_episodes(*podcast_key).unwrap();
+        assert_eq!(episodes.len(), 1);
+        let (key, episode) = &episodes[0];
+        assert!(matches!(episode.progress, Progress::Listening(p) if p == 42.));
+        let ext = pod_db.get_episode_ext(*key).unwrap();
+        assert_eq!(ext.stream_url, "https://example.org/1.mp3");
+        assert_eq!(ext.mime_type, None);
+        assert!(ext.chapters.is_empty());
+        assert!(!has_tree(&db, v0_1::BASIC));
+        assert!(!has_tree(&db, v0_1::EXTENDED));
+    }
+}
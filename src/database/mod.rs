@@ -1,13 +1,20 @@
 use eyre::{Result, WrapErr};
 use std::path::PathBuf;
 
+mod downloads;
 mod error;
+mod migrate;
 mod podcasts;
+mod queue;
+mod settings;
 mod types;
 
+pub use downloads::{DownloadQueue, QueuedDownload};
 pub use error::Error;
 pub use podcasts::{EpisodeKey, PodcastDb, PodcastKey};
-pub use types::{Date, Episode, EpisodeExt, Podcast, Progress};
+pub use queue::PlayQueue;
+pub use settings::{EndAction, Settings, Shortcut};
+pub use types::{CacheHeaders, Chapter, Date, Episode, EpisodeExt, Podcast, Progress};
 
 pub fn open() -> Result<sled::Db> {
     let path = PathBuf::from("database");
@@ -18,5 +25,6 @@ pub fn open() -> Result<sled::Db> {
     let db = config
         .open()
         .wrap_err_with(|| format!("Could not open database on {:?}", path))?;
+    migrate::run(&db).wrap_err("Could not migrate database to the current version")?;
     Ok(db)
 }
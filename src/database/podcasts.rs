@@ -1,38 +1,38 @@
 use super::error::Error;
-use super::types::{Date, Episode, EpisodeExt, Podcast, Progress};
+use super::types::{CacheHeaders, Chapter, Date, Episode, EpisodeExt, Podcast, Progress};
+use crate::feed::Refreshed;
 
 // TODO FIXME rewrite using From trait, EpisodeKey should use From PodcastKey
 
+/// keys derived from this hash are stored on disk, it must therefore
+/// not change between rust releases (unlike `DefaultHasher`)
 fn hash_str(s: impl AsRef<str>) -> u64 {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+    use sha1::{Digest, Sha1};
 
-    let mut hasher = DefaultHasher::new();
-    s.as_ref().hash(&mut hasher);
-    hasher.finish()
+    let hash = Sha1::digest(s.as_ref().as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    u64::from_be_bytes(bytes)
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct PodcastKey([u8; 8]);
-
-impl From<&Podcast> for PodcastKey {
-    fn from(podcast: &Podcast) -> Self {
-        let hash = hash_str(podcast.title.as_str());
-        Self(hash.to_be_bytes())
+/// normalize the url so trivially different urls for
+/// the same feed result in the same key
+fn canonical_url(url: &str) -> String {
+    match url::Url::parse(url.trim()) {
+        Ok(mut url) => {
+            url.set_fragment(None);
+            url.as_str().trim_end_matches('/').to_owned()
+        }
+        Err(_) => url.trim().to_owned(),
     }
 }
 
-impl From<&str> for PodcastKey {
-    fn from(podcast: &str) -> Self {
-        let hash = hash_str(podcast);
-        Self(hash.to_be_bytes())
-    }
-}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PodcastKey([u8; 8]);
 
-impl From<String> for PodcastKey {
-    fn from(podcast: String) -> Self {
-        let hash = hash_str(podcast.as_str());
-        Self(hash.to_be_bytes())
+impl std::fmt::Display for PodcastKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", u64::from_be_bytes(self.0))
     }
 }
 
@@ -51,6 +51,12 @@ impl From<sled::IVec> for PodcastKey {
 }
 
 impl PodcastKey {
+    /// podcasts are identified by the feed url they were subscribed with,
+    /// the key does not change if the title or url changes later
+    pub fn from_url(url: &str) -> Self {
+        let hash = hash_str(canonical_url(url));
+        Self(hash.to_be_bytes())
+    }
     fn podcast_end(&self) -> Self {
         self.increment()
     }
@@ -73,17 +79,30 @@ impl AsRef<[u8]> for PodcastKey {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EpisodeKey([u8; 16]);
 impl EpisodeKey {
-    pub fn from_title(podcast_id: impl Into<PodcastKey>, episode: impl AsRef<str>) -> Self {
+    fn from_id(podcast_id: impl Into<PodcastKey>, episode_id: impl AsRef<str>) -> Self {
         let mut key = [0u8; 16];
         let id = podcast_id.into().0;
         key[0..8].copy_from_slice(&id);
-        let id = hash_str(episode).to_be_bytes();
+        let id = hash_str(episode_id).to_be_bytes();
         key[8..16].copy_from_slice(&id);
         EpisodeKey(key)
     }
+    /// key on the guid, falling back to the stream url then the title
+    pub fn from_episode(podcast_id: impl Into<PodcastKey>, episode: &EpisodeExt) -> Self {
+        Self::from_id(podcast_id, episode.id())
+    }
+    pub fn podcast(&self) -> PodcastKey {
+        PodcastKey::from(&self.0[0..8])
+    }
+    /// the same episode under another podcast key
+    pub(super) fn with_podcast(&self, podcast_id: PodcastKey) -> Self {
+        let mut key = self.0;
+        key[0..8].copy_from_slice(&podcast_id.0);
+        EpisodeKey(key)
+    }
     fn podcast_start(podcast_id: impl Into<PodcastKey>) -> Self {
         let mut key = [0u8; 16];
         let id = podcast_id.into().0;
@@ -98,6 +117,34 @@ impl EpisodeKey {
     }
 }
 
+impl std::fmt::Display for EpisodeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:032x}", u128::from_be_bytes(self.0))
+    }
+}
+
+/// parses the hexadecimal form written by `Display`
+impl std::str::FromStr for EpisodeKey {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(|key| EpisodeKey(key.to_be_bytes()))
+    }
+}
+
+impl From<&[u8]> for EpisodeKey {
+    fn from(slice: &[u8]) -> Self {
+        let mut id = [0u8; 16];
+        id[0..16].copy_from_slice(slice);
+        Self(id)
+    }
+}
+
+impl From<sled::IVec> for EpisodeKey {
+    fn from(vec: sled::IVec) -> Self {
+        EpisodeKey::from(vec.as_ref())
+    }
+}
+
 impl AsRef<[u8]> for EpisodeKey {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -106,15 +153,25 @@ impl AsRef<[u8]> for EpisodeKey {
 
 #[derive(Debug, Clone)]
 pub struct PodcastDb {
-    basic: sled::Tree,
-    extended: sled::Tree,
+    pub(super) basic: sled::Tree,
+    pub(super) extended: sled::Tree,
+    pub(super) cache: sled::Tree,
+    /// artwork url per podcast
+    pub(super) artwork: sled::Tree,
 }
 
 impl PodcastDb {
     pub fn open(db: &sled::Db) -> sled::Result<Self> {
-        let basic = db.open_tree("podcasts_b_0.1")?;
-        let extended = db.open_tree("podcasts_e_0.1")?;
-        Ok(Self { basic, extended })
+        let basic = db.open_tree("podcasts_b_0.2")?;
+        let extended = db.open_tree("podcasts_e_0.2")?;
+        let cache = db.open_tree("feed_cache_0.1")?;
+        let artwork = db.open_tree("artwork_0.1")?;
+        Ok(Self {
+            basic,
+            extended,
+            cache,
+            artwork,
+        })
     }
     fn next_podcast(&self, id: PodcastKey) -> Result<Option<(PodcastKey, Podcast)>, Error> {
         let res = self.basic.get_gt(id)?;
@@ -129,16 +186,15 @@ impl PodcastDb {
 
         let podcast = bincode::deserialize(&value).unwrap();
         let id = PodcastKey::from(key_bytes);
-        let id = id.increment(); // make sure we get another podcast next call
         Ok(Some((id, podcast)))
     }
 
-    pub fn get_podcasts(&self) -> Result<Vec<Podcast>, Error> {
+    pub fn get_podcasts(&self) -> Result<Vec<(PodcastKey, Podcast)>, Error> {
         let mut list = Vec::new();
         let mut id = PodcastKey([0u8; 8]);
-        while let Some((next_id, podcast)) = self.next_podcast(id)? {
-            list.push(podcast);
-            id = next_id;
+        while let Some((key, podcast)) = self.next_podcast(id)? {
+            list.push((key, podcast));
+            id = key.increment(); // make sure we get another podcast next call
         }
         Ok(list)
     }
@@ -152,25 +208,103 @@ impl PodcastDb {
         Ok(podcast)
     }
 
-    pub fn add_podcast(&self, podcast: &Podcast) -> Result<(), Error> {
-        let podcast_id = PodcastKey::from(podcast);
+    pub fn add_podcast(&self, podcast: &Podcast) -> Result<PodcastKey, Error> {
+        let podcast_id = PodcastKey::from_url(&podcast.url);
+        self.update_podcast(podcast_id, podcast)?;
+        Ok(podcast_id)
+    }
+
+    /// removes the podcast, all its episodes and the cached feed headers
+    pub fn remove_podcast(&self, podcast_id: PodcastKey) -> Result<(), Error> {
+        let start = EpisodeKey::podcast_start(podcast_id);
+        let end = EpisodeKey::podcast_end(podcast_id);
+        for tree in &[&self.basic, &self.extended] {
+            let mut batch = sled::Batch::default();
+            for key in tree.range(start..end).keys() {
+                batch.remove(key?);
+            }
+            tree.apply_batch(batch)?;
+        }
+        self.basic.remove(podcast_id)?;
+        self.cache.remove(podcast_id)?;
+        self.artwork.remove(podcast_id)?;
+        Ok(())
+    }
+
+    /// store changed podcast info (title, url) keeping the key
+    pub fn update_podcast(&self, podcast_id: PodcastKey, podcast: &Podcast) -> Result<(), Error> {
         let bytes = bincode::serialize(&podcast).unwrap();
         self.basic.insert(podcast_id, bytes)?;
         Ok(())
     }
 
-    pub fn get_episodes(&self, podcast_id: impl Into<PodcastKey>) -> Result<Vec<Episode>, Error> {
+    pub fn get_cache_headers(
+        &self,
+        podcast_id: impl Into<PodcastKey>,
+    ) -> Result<Option<CacheHeaders>, Error> {
+        let headers = self
+            .cache
+            .get(podcast_id.into())?
+            .map(|bytes| bincode::deserialize(&bytes).unwrap());
+        Ok(headers)
+    }
+
+    pub fn set_cache_headers(
+        &self,
+        podcast_id: impl Into<PodcastKey>,
+        headers: &CacheHeaders,
+    ) -> Result<(), Error> {
+        let bytes = bincode::serialize(headers).unwrap();
+        self.cache.insert(podcast_id.into(), bytes)?;
+        Ok(())
+    }
+
+    pub fn get_artwork(&self, podcast_id: impl Into<PodcastKey>) -> Result<Option<String>, Error> {
+        let url = self
+            .artwork
+            .get(podcast_id.into())?
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        Ok(url)
+    }
+
+    /// removes the artwork if the feed no longer has any
+    pub fn set_artwork(
+        &self,
+        podcast_id: impl Into<PodcastKey>,
+        url: Option<&str>,
+    ) -> Result<(), Error> {
+        match url {
+            Some(url) => self.artwork.insert(podcast_id.into(), url.as_bytes())?,
+            None => self.artwork.remove(podcast_id.into())?,
+        };
+        Ok(())
+    }
+
+    pub fn get_episodes(
+        &self,
+        podcast_id: impl Into<PodcastKey>,
+    ) -> Result<Vec<(EpisodeKey, Episode)>, Error> {
         let podcast_key = podcast_id.into();
         let start = EpisodeKey::podcast_start(podcast_key);
         let end = EpisodeKey::podcast_end(podcast_key);
-        let mut list: Vec<Episode> = Vec::new();
-        for value in self.basic.range(start..end).values() {
-            let episode = bincode::deserialize(&value?).unwrap();
-            list.push(episode);
+        let mut list = Vec::new();
+        for res in self.basic.range(start..end) {
+            let (key, value) = res?;
+            let episode = bincode::deserialize(&value).unwrap();
+            list.push((EpisodeKey::from(key), episode));
         }
         Ok(list)
     }
 
+    pub fn get_episode(&self, episode_id: impl Into<EpisodeKey>) -> Result<Episode, Error> {
+        let bytes = self
+            .basic
+            .get(episode_id.into())?
+            .ok_or(Error::NotInDatabase)?;
+        let episode = bincode::deserialize(&bytes).unwrap();
+        Ok(episode)
+    }
+
     pub fn get_episode_ext(&self, episode_id: impl Into<EpisodeKey>) -> Result<EpisodeExt, Error> {
         let bytes = self
             .extended
@@ -180,6 +314,25 @@ impl PodcastDb {
         Ok(episode)
     }
 
+    /// the oldest episode of the same podcast that is newer then the given
+    /// episode and was not completed
+    pub fn next_unplayed(
+        &self,
+        episode_id: EpisodeKey,
+    ) -> Result<Option<(EpisodeKey, Episode)>, Error> {
+        let episodes = self.get_episodes(episode_id.podcast())?;
+        let current = match episodes.iter().find(|(key, _)| *key == episode_id) {
+            Some((_, episode)) => *episode.date.inner(),
+            None => return Err(Error::NotInDatabase),
+        };
+        let next = episodes
+            .into_iter()
+            .filter(|(_, e)| *e.date.inner() > current)
+            .filter(|(_, e)| !matches!(e.progress, Progress::Completed))
+            .min_by_key(|(_, e)| *e.date.inner());
+        Ok(next)
+    }
+
     fn update_progress(progress: &Progress, old: Option<&[u8]>) -> impl Into<sled::IVec> {
         let old = old.expect("item should be in database to update progress");
         let mut episode: Episode = bincode::deserialize(&old).unwrap();
@@ -208,8 +361,93 @@ impl PodcastDb {
         bincode::serialize(&new).unwrap()
     }
 
-    fn update_extended(new: &EpisodeExt, _: Option<&[u8]>) -> impl Into<sled::IVec> {
-        bincode::serialize(new).unwrap()
+    /// chapters are fetched or read from the downloaded file after the
+    /// feed is parsed, keep them unless the publisher moved them
+    fn update_extended(new: &EpisodeExt, old: Option<&[u8]>) -> impl Into<sled::IVec> {
+        let existing: Option<EpisodeExt> = old.map(|old| bincode::deserialize(old).unwrap());
+        match existing {
+            Some(existing)
+                if new.chapters.is_empty() && new.chapters_url == existing.chapters_url =>
+            {
+                let new = EpisodeExt {
+                    chapters: existing.chapters,
+                    chapters_fetched: existing.chapters_fetched,
+                    ..new.clone()
+                };
+                bincode::serialize(&new).unwrap()
+            }
+            _ => bincode::serialize(new).unwrap(),
+        }
+    }
+
+    pub fn set_chapters(&self, key: EpisodeKey, chapters: Vec<Chapter>) -> Result<(), Error> {
+        self.extended.fetch_and_update(key, |old| {
+            let mut episode: EpisodeExt = bincode::deserialize(old?).unwrap();
+            episode.chapters = chapters.clone();
+            Some(bincode::serialize(&episode).unwrap())
+        })?;
+        Ok(())
+    }
+
+    /// stores the chapters found at the chapters url, none are found if
+    /// fetching failed. Chapters read from the file are kept then.
+    pub fn set_fetched_chapters(
+        &self,
+        key: EpisodeKey,
+        chapters: Vec<Chapter>,
+    ) -> Result<(), Error> {
+        self.extended.fetch_and_update(key, |old| {
+            let mut episode: EpisodeExt = bincode::deserialize(old?).unwrap();
+            if !chapters.is_empty() {
+                episode.chapters = chapters.clone();
+            }
+            episode.chapters_fetched = true;
+            Some(bincode::serialize(&episode).unwrap())
+        })?;
+        Ok(())
+    }
+
+    /// episodes with a chapters url that was not fetched yet and their
+    /// url, newest first
+    pub fn missing_chapters(
+        &self,
+        podcast_id: PodcastKey,
+    ) -> Result<Vec<(EpisodeKey, String)>, Error> {
+        let mut episodes = self.get_episodes(podcast_id)?;
+        episodes.sort_by_key(|(_, e)| std::cmp::Reverse(*e.date.inner()));
+        let mut missing = Vec::new();
+        for (key, _) in episodes {
+            let episode = self.get_episode_ext(key)?;
+            match episode.chapters_url {
+                Some(url) if episode.chapters.is_empty() && !episode.chapters_fetched => {
+                    missing.push((key, url))
+                }
+                _ => (),
+            }
+        }
+        Ok(missing)
+    }
+
+    /// episodes migrated from before keys were based on the guid are keyed
+    /// on their stream url, move them to their guid based key
+    fn rekey_from_url(
+        &self,
+        podcast_id: PodcastKey,
+        key: EpisodeKey,
+        episode: &EpisodeExt,
+    ) -> Result<(), Error> {
+        let legacy = EpisodeKey::from_id(podcast_id, &episode.stream_url);
+        if legacy == key || self.basic.contains_key(key)? {
+            return Ok(());
+        }
+        if let Some(basic) = self.basic.remove(legacy)? {
+            self.basic.insert(key, basic)?;
+            if let Some(extended) = self.extended.remove(legacy)? {
+                self.extended.insert(key, extended)?;
+            }
+            crate::download::move_downloads(&legacy.to_string(), key);
+        }
+        Ok(())
     }
 
     pub fn update_episodes(
@@ -219,7 +457,8 @@ impl PodcastDb {
     ) -> Result<(), Error> {
         let podcast_id: PodcastKey = podcast_id.into();
         for new in new_list {
-            let key = EpisodeKey::from_title(podcast_id, &new.title);
+            let key = EpisodeKey::from_episode(podcast_id, &new);
+            self.rekey_from_url(podcast_id, key, &new)?;
             self.basic
                 .fetch_and_update(key, |old| Some(Self::update_basic(&new, old)))?;
             self.extended
@@ -228,14 +467,148 @@ impl PodcastDb {
         Ok(())
     }
 
-    pub async fn update_podcasts(&self) -> Result<(), Error> {
-        use crate::feed::add_podcast;
+    /// refresh every feed, returns how each podcast (by title) was refreshed
+    pub async fn update_podcasts(&self) -> Result<Vec<(String, Refreshed)>, Error> {
+        use crate::feed::refresh_podcast;
 
+        let mut results = Vec::new();
         let mut id = PodcastKey([0u8; 8]);
-        while let Some((next_id, podcast)) = self.next_podcast(id)? {
-            add_podcast(self.clone(), podcast.url.clone()).await;
-            id = next_id;
+        while let Some((key, podcast)) = self.next_podcast(id)? {
+            let refreshed = refresh_podcast(self, key, &podcast).await;
+            results.push((podcast.title, refreshed));
+            id = key.increment(); // make sure we get another podcast next call
         }
-        Ok(())
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn podcast_key_ignores_trivial_url_differences() {
+        let key = PodcastKey::from_url("https://example.org/feed");
+        assert_eq!(key, PodcastKey::from_url("https://Example.org/feed/"));
+        assert_eq!(
+            key,
+            PodcastKey::from_url(" https://example.org/feed#latest")
+        );
+        assert_ne!(key, PodcastKey::from_url("https://example.org/other_feed"));
+    }
+
+    #[test]
+    fn episode_key_parses_from_file_name() {
+        let podcast = PodcastKey::from_url("https://example.org/feed");
+        let key = EpisodeKey::from_id(podcast, "guid");
+        assert_eq!(key.to_string().parse(), Ok(key));
+        assert!("Episode 1".parse::<EpisodeKey>().is_err());
+    }
+
+    #[test]
+    fn next_unplayed_skips_completed() {
+        use chrono::{Duration, Utc};
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let pod_db = PodcastDb::open(&db).unwrap();
+        let podcast = PodcastKey::from_url("https://example.org/feed");
+        let now = Utc::now();
+        let episodes = (0..3)
+            .map(|i| EpisodeExt {
+                guid: Some(i.to_string()),
+                stream_url: format!("https://example.org/{}.mp3", i),
+                mime_type: None,
+                duration: 60.,
+                title: format!("Episode {}", i),
+                podcast: "Example".to_owned(),
+                date: Date::Publication(now + Duration::days(i)),
+                chapters_url: None,
+                chapters: Vec::new(),
+                chapters_fetched: false,
+            })
+            .collect();
+        pod_db.update_episodes(podcast, episodes).unwrap();
+        let key = |i: i64| EpisodeKey::from_id(podcast, i.to_string());
+
+        let (next, _) = pod_db.next_unplayed(key(0)).unwrap().unwrap();
+        assert_eq!(next, key(1));
+        pod_db.update_episode_progress(key(1), Progress::Completed);
+        let (next, _) = pod_db.next_unplayed(key(0)).unwrap().unwrap();
+        assert_eq!(next, key(2));
+        assert!(pod_db.next_unplayed(key(2)).unwrap().is_none());
+    }
+
+    #[test]
+    fn refresh_keeps_chapters() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let pod_db = PodcastDb::open(&db).unwrap();
+        let podcast = PodcastKey::from_url("https://example.org/feed");
+        let episode = |chapters_url: &str| EpisodeExt {
+            guid: Some("1".to_owned()),
+            stream_url: "https://example.org/1.mp3".to_owned(),
+            mime_type: None,
+            duration: 60.,
+            title: "Episode 1".to_owned(),
+            podcast: "Example".to_owned(),
+            date: Date::Added(chrono::Utc::now()),
+            chapters_url: Some(chapters_url.to_owned()),
+            chapters: Vec::new(),
+            chapters_fetched: false,
+        };
+        let key = EpisodeKey::from_id(podcast, "1");
+        pod_db
+            .update_episodes(podcast, vec![episode("a.json")])
+            .unwrap();
+        let chapters = vec![Chapter {
+            start: 0.,
+            title: "Intro".to_owned(),
+        }];
+        pod_db.set_chapters(key, chapters.clone()).unwrap();
+
+        pod_db
+            .update_episodes(podcast, vec![episode("a.json")])
+            .unwrap();
+        assert_eq!(pod_db.get_episode_ext(key).unwrap().chapters, chapters);
+        // moved chapters have to be fetched again
+        pod_db
+            .update_episodes(podcast, vec![episode("b.json")])
+            .unwrap();
+        assert!(pod_db.get_episode_ext(key).unwrap().chapters.is_empty());
+    }
+
+    #[test]
+    fn failed_chapters_are_not_fetched_again() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let pod_db = PodcastDb::open(&db).unwrap();
+        let podcast = PodcastKey::from_url("https://example.org/feed");
+        let episode = |chapters_url: &str| EpisodeExt {
+            guid: Some("1".to_owned()),
+            stream_url: "https://example.org/1.mp3".to_owned(),
+            mime_type: None,
+            duration: 60.,
+            title: "Episode 1".to_owned(),
+            podcast: "Example".to_owned(),
+            date: Date::Added(chrono::Utc::now()),
+            chapters_url: Some(chapters_url.to_owned()),
+            chapters: Vec::new(),
+            chapters_fetched: false,
+        };
+        let key = EpisodeKey::from_id(podcast, "1");
+        pod_db
+            .update_episodes(podcast, vec![episode("a.json")])
+            .unwrap();
+        let missing = pod_db.missing_chapters(podcast).unwrap();
+        assert_eq!(missing, vec![(key, "a.json".to_owned())]);
+
+        pod_db.set_fetched_chapters(key, Vec::new()).unwrap();
+        pod_db
+            .update_episodes(podcast, vec![episode("a.json")])
+            .unwrap();
+        assert!(pod_db.missing_chapters(podcast).unwrap().is_empty());
+        // a new url is tried
+        pod_db
+            .update_episodes(podcast, vec![episode("b.json")])
+            .unwrap();
+        assert_eq!(pod_db.missing_chapters(podcast).unwrap().len(), 1);
     }
 }
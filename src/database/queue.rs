@@ -0,0 +1,111 @@
+use super::error::Error;
+use super::podcasts::EpisodeKey;
+
+/// episodes to play after the current one, keyed on their place in
+/// the queue (big endian so sled keeps them in order)
+#[derive(Clone, Debug)]
+pub struct PlayQueue {
+    tree: sled::Tree,
+}
+
+impl PlayQueue {
+    pub fn open(db: &sled::Db) -> sled::Result<Self> {
+        let tree = db.open_tree("play_queue_0.1")?;
+        Ok(Self { tree })
+    }
+    pub fn get_all(&self) -> Result<Vec<EpisodeKey>, Error> {
+        let mut list = Vec::new();
+        for res in self.tree.iter() {
+            let (_, key) = res?;
+            list.push(EpisodeKey::from(key));
+        }
+        Ok(list)
+    }
+    /// replaces the queue in one go so it is never half written
+    fn store(&self, list: &[EpisodeKey]) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        for res in self.tree.iter().keys() {
+            batch.remove(res?);
+        }
+        for (place, key) in list.iter().enumerate() {
+            batch.insert(&(place as u32).to_be_bytes()[..], key.as_ref());
+        }
+        self.tree.apply_batch(batch)?;
+        Ok(())
+    }
+    fn modify(&self, f: impl FnOnce(&mut Vec<EpisodeKey>)) -> Result<(), Error> {
+        let mut list = self.get_all()?;
+        f(&mut list);
+        self.store(&list)
+    }
+    /// play after the current episode, moves it if it was queued already
+    pub fn push_front(&self, episode: EpisodeKey) -> Result<(), Error> {
+        self.modify(|list| {
+            list.retain(|k| *k != episode);
+            list.insert(0, episode);
+        })
+    }
+    /// play after everything else, moves it if it was queued already
+    pub fn push_back(&self, episode: EpisodeKey) -> Result<(), Error> {
+        self.modify(|list| {
+            list.retain(|k| *k != episode);
+            list.push(episode);
+        })
+    }
+    pub fn pop_front(&self) -> Result<Option<EpisodeKey>, Error> {
+        let mut first = None;
+        self.modify(|list| {
+            if !list.is_empty() {
+                first = Some(list.remove(0));
+            }
+        })?;
+        Ok(first)
+    }
+    pub fn remove(&self, episode: EpisodeKey) -> Result<(), Error> {
+        self.modify(|list| list.retain(|k| *k != episode))
+    }
+    /// move an episode up (negative) or down (positive) the queue
+    pub fn shift(&self, episode: EpisodeKey, by: i32) -> Result<(), Error> {
+        self.modify(|list| {
+            if let Some(idx) = list.iter().position(|k| *k == episode) {
+                let target = (idx as i64 + by as i64).clamp(0, list.len() as i64 - 1);
+                let key = list.remove(idx);
+                list.insert(target as usize, key);
+            }
+        })
+    }
+    pub fn clear(&self) -> Result<(), Error> {
+        self.tree.clear()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(n: u8) -> EpisodeKey {
+        EpisodeKey::from(&[n; 16][..])
+    }
+
+    #[test]
+    fn queue_operations_keep_order() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let queue = PlayQueue::open(&db).unwrap();
+        queue.push_back(key(1)).unwrap();
+        queue.push_back(key(2)).unwrap();
+        queue.push_front(key(3)).unwrap();
+        assert_eq!(queue.get_all().unwrap(), [key(3), key(1), key(2)]);
+
+        queue.shift(key(2), -1).unwrap();
+        assert_eq!(queue.get_all().unwrap(), [key(3), key(2), key(1)]);
+        queue.push_back(key(3)).unwrap();
+        assert_eq!(queue.get_all().unwrap(), [key(2), key(1), key(3)]);
+
+        assert_eq!(queue.pop_front().unwrap(), Some(key(2)));
+        queue.remove(key(3)).unwrap();
+        assert_eq!(queue.get_all().unwrap(), [key(1)]);
+        queue.clear().unwrap();
+        assert_eq!(queue.pop_front().unwrap(), None);
+    }
+}
@@ -0,0 +1,202 @@
+use super::error::Error;
+use super::podcasts::PodcastKey;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// user preferences, global ones are keyed on their name, per podcast
+/// ones on their name followed by the podcast key
+#[derive(Clone, Debug)]
+pub struct Settings {
+    tree: sled::Tree,
+}
+
+/// what to do when an episode finished playing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndAction {
+    Stop,
+    /// play the next newer episode of the podcast that was not completed
+    PlayNext,
+}
+
+impl EndAction {
+    pub fn next(self) -> Self {
+        match self {
+            EndAction::Stop => EndAction::PlayNext,
+            EndAction::PlayNext => EndAction::Stop,
+        }
+    }
+}
+
+/// actions that can be bound to a key
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shortcut {
+    SkipForward,
+    SkipBackward,
+    SpeedUp,
+    SpeedDown,
+    NextChapter,
+    PreviousChapter,
+}
+
+impl Shortcut {
+    pub const ALL: [Shortcut; 6] = [
+        Shortcut::SkipForward,
+        Shortcut::SkipBackward,
+        Shortcut::SpeedUp,
+        Shortcut::SpeedDown,
+        Shortcut::NextChapter,
+        Shortcut::PreviousChapter,
+    ];
+    pub fn description(&self) -> &'static str {
+        match self {
+            Shortcut::SkipForward => "skip forward",
+            Shortcut::SkipBackward => "skip backward",
+            Shortcut::SpeedUp => "speed up",
+            Shortcut::SpeedDown => "slow down",
+            Shortcut::NextChapter => "next chapter",
+            Shortcut::PreviousChapter => "previous chapter",
+        }
+    }
+    /// name of the key bound if the user did not change it
+    fn default_key(&self) -> &'static str {
+        match self {
+            Shortcut::SkipForward => "Right",
+            Shortcut::SkipBackward => "Left",
+            Shortcut::SpeedUp => "RBracket",
+            Shortcut::SpeedDown => "LBracket",
+            Shortcut::NextChapter => "PageDown",
+            Shortcut::PreviousChapter => "PageUp",
+        }
+    }
+}
+
+const SPEED: &[u8] = b"speed";
+const END_ACTION: &[u8] = b"end_action";
+const SHORTCUT: &[u8] = b"shortcut";
+
+fn shortcut_key(shortcut: Shortcut) -> Vec<u8> {
+    let mut key = SHORTCUT.to_vec();
+    key.push(b'/');
+    key.extend_from_slice(&bincode::serialize(&shortcut).unwrap());
+    key
+}
+
+fn podcast_key(name: &[u8], podcast_id: PodcastKey) -> Vec<u8> {
+    let mut key = name.to_vec();
+    key.push(b'/');
+    key.extend_from_slice(podcast_id.as_ref());
+    key
+}
+
+impl Settings {
+    pub fn open(db: &sled::Db) -> sled::Result<Self> {
+        let tree = db.open_tree("settings_0.1")?;
+        Ok(Self { tree })
+    }
+    fn get<T: DeserializeOwned>(&self, key: &[u8]) -> Option<T> {
+        match self.tree.get(key) {
+            Ok(value) => value.map(|bytes| bincode::deserialize(&bytes).unwrap()),
+            Err(e) => {
+                log::error!("could not read setting: {}", e);
+                None
+            }
+        }
+    }
+    /// removes the setting if the value is None
+    fn set<T: Serialize>(&self, key: &[u8], value: Option<T>) -> Result<(), Error> {
+        match value {
+            Some(value) => self.tree.insert(key, bincode::serialize(&value).unwrap())?,
+            None => self.tree.remove(key)?,
+        };
+        Ok(())
+    }
+
+    pub fn global_speed(&self) -> f32 {
+        self.get(SPEED).unwrap_or(1.0)
+    }
+    pub fn set_global_speed(&self, speed: f32) -> Result<(), Error> {
+        self.set(SPEED, Some(speed))
+    }
+    /// speed for this podcast if it differs from the global speed
+    pub fn podcast_speed(&self, podcast_id: PodcastKey) -> Option<f32> {
+        self.get(&podcast_key(SPEED, podcast_id))
+    }
+    pub fn set_podcast_speed(
+        &self,
+        podcast_id: PodcastKey,
+        speed: Option<f32>,
+    ) -> Result<(), Error> {
+        self.set(&podcast_key(SPEED, podcast_id), speed)
+    }
+    /// the speed episodes of this podcast should play at
+    pub fn speed(&self, podcast_id: PodcastKey) -> f32 {
+        self.podcast_speed(podcast_id)
+            .unwrap_or_else(|| self.global_speed())
+    }
+    pub fn end_action(&self) -> EndAction {
+        self.get(END_ACTION).unwrap_or(EndAction::Stop)
+    }
+    pub fn set_end_action(&self, action: EndAction) -> Result<(), Error> {
+        self.set(END_ACTION, Some(action))
+    }
+    /// name of the key that triggers the shortcut, empty if unbound
+    pub fn shortcut(&self, shortcut: Shortcut) -> String {
+        self.get(&shortcut_key(shortcut))
+            .unwrap_or_else(|| shortcut.default_key().to_owned())
+    }
+    /// binds the key, unbinding it from any other shortcut
+    pub fn set_shortcut(&self, shortcut: Shortcut, key: &str) -> Result<(), Error> {
+        for other in Shortcut::ALL.iter().filter(|s| **s != shortcut) {
+            if self.shortcut(*other) == key {
+                self.set(&shortcut_key(*other), Some(""))?;
+            }
+        }
+        self.set(&shortcut_key(shortcut), Some(key))
+    }
+    /// the shortcut bound to a key
+    pub fn shortcut_for(&self, key: &str) -> Option<Shortcut> {
+        if key.is_empty() {
+            return None;
+        }
+        Shortcut::ALL
+            .iter()
+            .copied()
+            .find(|s| self.shortcut(*s) == key)
+    }
+    /// forget all settings specific to a podcast
+    pub fn remove_podcast(&self, podcast_id: PodcastKey) -> Result<(), Error> {
+        self.set_podcast_speed(podcast_id, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn podcast_speed_overrides_global() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let settings = Settings::open(&db).unwrap();
+        let podcast = PodcastKey::from_url("https://example.org/feed");
+        assert_eq!(settings.speed(podcast), 1.0);
+
+        settings.set_global_speed(1.5).unwrap();
+        assert_eq!(settings.speed(podcast), 1.5);
+        settings.set_podcast_speed(podcast, Some(2.0)).unwrap();
+        assert_eq!(settings.speed(podcast), 2.0);
+        settings.remove_podcast(podcast).unwrap();
+        assert_eq!(settings.speed(podcast), 1.5);
+    }
+
+    #[test]
+    fn rebinding_a_key_moves_it() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let settings = Settings::open(&db).unwrap();
+        assert_eq!(settings.shortcut_for("Right"), Some(Shortcut::SkipForward));
+
+        settings.set_shortcut(Shortcut::SpeedUp, "Right").unwrap();
+        assert_eq!(settings.shortcut_for("Right"), Some(Shortcut::SpeedUp));
+        assert_eq!(settings.shortcut(Shortcut::SkipForward), "");
+        assert_eq!(settings.shortcut_for(""), None);
+    }
+}
@@ -15,6 +15,9 @@ impl Date {
             .map(Result::ok)
             .flatten()
             .map(DateTime::from); // convert to Utc
+        Self::from_published(pub_date)
+    }
+    pub fn from_published(pub_date: Option<DateTime<Utc>>) -> Date {
         match pub_date {
             Some(date) => Date::Publication(date),
             None => Date::Added(Utc::now()),
@@ -60,30 +63,62 @@ impl From<&EpisodeExt> for Episode {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// part of an episode as marked by the publisher
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// in seconds from the start of the episode
+    pub start: f32,
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EpisodeExt {
+    /// unique identifier given by the feed (rss guid, atom or json feed id)
+    pub guid: Option<String>,
     pub stream_url: String,
-    /// the duration of the episode in seconds
+    /// media type of the audio as announced by the feed
+    pub mime_type: Option<String>,
+    /// the duration of the episode in seconds, zero if the feed does
+    /// not say
     pub duration: f32,
     pub title: String,
     pub podcast: String,
     pub date: Date,
-    // some extra fields
+    /// where the publisher offers chapters (`podcast:chapters`)
+    pub chapters_url: Option<String>,
+    /// from the chapters url or the downloaded file, sorted by start
+    pub chapters: Vec<Chapter>,
+    /// the chapters url was fetched, also set if that failed or listed
+    /// no chapters so it is not fetched on every refresh
+    pub chapters_fetched: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl EpisodeExt {
+    pub fn known_duration(&self) -> Option<f32> {
+        Some(self.duration).filter(|d| *d > 0.)
+    }
+    /// the most stable identifier we have for this episode
+    pub fn id(&self) -> &str {
+        match &self.guid {
+            Some(guid) if !guid.is_empty() => guid,
+            _ if !self.stream_url.is_empty() => &self.stream_url,
+            _ => &self.title,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Podcast {
     pub title: String,
     pub url: String,
 }
 
-impl Podcast {
-    pub fn from_url(channel: &rss::Channel, url: String) -> Self {
-        Self {
-            title: channel.title().to_owned(),
-            url,
-        }
-    }
+/// http caching headers of the last feed response, send back
+/// on refresh so an unchanged feed does not have to be downloaded
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CacheHeaders {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
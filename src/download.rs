@@ -1,40 +1,191 @@
-use crate::database::{EpisodeExt, EpisodeKey};
+use crate::database::{DownloadQueue, EpisodeExt, EpisodeKey, PodcastKey, QueuedDownload};
 use crate::{database, Message};
 use iced::Subscription;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+mod filetype;
+mod id3;
 mod subscribe;
-pub use subscribe::Progress;
+pub use filetype::FileType;
+pub use id3::read_chapters;
+pub use subscribe::{Error, Progress};
 
 #[derive(Clone, Debug)]
 pub struct Download {
+    key: EpisodeKey,
     url: reqwest::Url,
     path: PathBuf,
 }
 
-#[derive(Default)]
 pub struct Downloader {
     downloading: Vec<Download>,
+    paused: Vec<Download>,
+    failed: Vec<Download>,
+    queue: DownloadQueue,
+}
+
+/// removes the download with the given key from the list
+fn take(list: &mut Vec<Download>, id: EpisodeKey) -> Option<Download> {
+    let idx = list.iter().position(|d| d.key == id)?;
+    Some(list.remove(idx))
 }
 
 impl Downloader {
-    pub fn add(&mut self, id: EpisodeKey, db: &mut database::PodcastDb) -> iced::Command<Message> {
+    /// restores the downloads that were queued when the app was closed,
+    /// see `orphaned_parts` for downloads that were not queued
+    pub fn from_db(queue: DownloadQueue, pod_db: &database::PodcastDb) -> Self {
+        let mut downloader = Self {
+            downloading: Vec::new(),
+            paused: Vec::new(),
+            failed: Vec::new(),
+            queue,
+        };
+        for (key, mut queued) in downloader.queue.get_all().unwrap() {
+            // downloads queued before files were named by episode key
+            // have an outdated path
+            let current = pod_db.get_episode_ext(key).ok();
+            if let Some((_, path)) = current.and_then(|e| part_path(key, &e)) {
+                queued.path = path;
+            }
+            let mut finished = queued.path.clone();
+            finished.set_extension(""); // this removes the .part
+            let finished = FileType::ALL
+                .iter()
+                .any(|t| finished.with_extension(t.as_str()).is_file());
+            let url = reqwest::Url::parse(&queued.url);
+            if finished || url.is_err() {
+                // finished right before the app closed or can never start
+                downloader.queue.remove(key).unwrap();
+                continue;
+            }
+            let download = Download {
+                key,
+                url: url.unwrap(),
+                path: queued.path,
+            };
+            if queued.paused {
+                downloader.paused.push(download);
+            } else {
+                downloader.downloading.push(download);
+            }
+        }
+        downloader
+    }
+    /// queues the orphaned partial downloads (see: `orphaned_parts`),
+    /// returns those that were not queued already
+    pub fn resume_orphaned(
+        &mut self,
+        keys: Vec<EpisodeKey>,
+        pod_db: &database::PodcastDb,
+    ) -> Vec<EpisodeKey> {
+        let mut resumed = Vec::new();
+        for key in keys {
+            if self.all().any(|d| d.key == key) {
+                continue;
+            }
+            log::info!("resuming orphaned partial download of: {}", key);
+            self.add(key, pod_db);
+            resumed.push(key);
+        }
+        resumed
+    }
+    fn all(&self) -> impl Iterator<Item = &Download> {
+        self.downloading
+            .iter()
+            .chain(self.paused.iter())
+            .chain(self.failed.iter())
+    }
+    /// the downloads that are not finished and whether they are paused
+    pub fn queued(&self) -> impl Iterator<Item = (EpisodeKey, bool)> + '_ {
+        let downloading = self.downloading.iter().map(|d| (d.key, false));
+        let paused = self.paused.iter().map(|d| (d.key, true));
+        downloading.chain(paused)
+    }
+    pub fn add(&mut self, id: EpisodeKey, db: &database::PodcastDb) -> iced::Command<Message> {
+        if self.failed.iter().any(|d| d.key == id) {
+            self.retry(id);
+            return iced::Command::none();
+        }
+        if self.all().any(|d| d.key == id) {
+            return iced::Command::none();
+        }
         let episode = db
             .get_episode_ext(id)
             .expect("item should be in database when we start downloading");
-        let url = reqwest::Url::parse(&episode.stream_url).expect("url should be valid here");
-        let extension = url
-            .path()
-            .rsplitn(2, '.')
-            .next()
-            .expect("there has to be a file extension");
-        let mut path = base_file_path(&episode);
-        path.set_extension(&format!("{}.part", extension));
-        let dl = Download { path, url };
+        let (url, path) = part_path(id, &episode).expect("url should be valid here");
+        let queued = QueuedDownload {
+            url: url.to_string(),
+            path: path.clone(),
+            added: chrono::Utc::now(),
+            paused: false,
+        };
+        if let Err(e) = self.queue.add(id, &queued) {
+            log::error!("could not store download in queue: {}", e);
+        }
+        let dl = Download { key: id, path, url };
         self.downloading.push(dl);
         iced::Command::none()
     }
+    /// removes a finished download from the queue
+    pub fn finished(&mut self, id: EpisodeKey) {
+        self.downloading.retain(|d| d.key != id);
+        if let Err(e) = self.queue.remove(id) {
+            log::error!("could not remove download from queue: {}", e);
+        }
+    }
+    /// stops a download that failed, it stays in the stored queue
+    /// and is tried again on the next start
+    pub fn errored(&mut self, id: EpisodeKey) {
+        if let Some(download) = take(&mut self.downloading, id) {
+            self.failed.push(download);
+        }
+    }
+    pub fn retry(&mut self, id: EpisodeKey) {
+        if let Some(download) = take(&mut self.failed, id) {
+            self.downloading.push(download);
+        }
+    }
+    /// stops the download keeping what was downloaded so far
+    pub fn pause(&mut self, id: EpisodeKey) {
+        if let Some(download) = take(&mut self.downloading, id) {
+            self.paused.push(download);
+            if let Err(e) = self.queue.set_paused(id, true) {
+                log::error!("could not store paused download: {}", e);
+            }
+        }
+    }
+    pub fn resume(&mut self, id: EpisodeKey) {
+        if let Some(download) = take(&mut self.paused, id) {
+            self.downloading.push(download);
+            if let Err(e) = self.queue.set_paused(id, false) {
+                log::error!("could not store resumed download: {}", e);
+            }
+        }
+    }
+    /// stops the download and removes what was downloaded so far
+    pub fn cancel(&mut self, id: EpisodeKey) -> iced::Command<Message> {
+        let download = take(&mut self.downloading, id)
+            .or_else(|| take(&mut self.paused, id))
+            .or_else(|| take(&mut self.failed, id));
+        if let Err(e) = self.queue.remove(id) {
+            log::error!("could not remove download from queue: {}", e);
+        }
+        match download {
+            Some(Download { path, .. }) => {
+                iced::Command::perform(remove_part(path), |_| Message::DownloadCancelled)
+            }
+            None => iced::Command::none(),
+        }
+    }
+    /// downloads of a podcast that are not finished, including paused
+    /// and failed ones
+    pub fn of_podcast(&self, podcast_id: PodcastKey) -> Vec<EpisodeKey> {
+        self.all()
+            .map(|d| d.key)
+            .filter(|key| key.podcast() == podcast_id)
+            .collect()
+    }
     pub fn subs(&self) -> Vec<Subscription<Message>> {
         const N: usize = 2; //number of downloads to handle simultaneously
 
@@ -46,58 +197,237 @@ impl Downloader {
             .collect()
     }
     fn subscribe(item: Download) -> iced::Subscription<Message> {
-        iced::Subscription::from_recipe(item).map(Message::DownloadProgress)
+        iced::Subscription::from_recipe(item)
+            .map(|(key, progress)| Message::DownloadProgress(key, progress))
     }
 }
 
-/// path to file without any extension
-pub fn base_file_path(episode: &EpisodeExt) -> PathBuf {
+/// the type of the downloaded file of an episode if there is one
+pub fn downloaded(id: EpisodeKey) -> Option<FileType> {
+    let base = base_file_path(id);
+    FileType::ALL.iter().copied().find(|file_type| {
+        let mut path = base.clone();
+        path.set_extension(file_type.as_str());
+        path.is_file()
+    })
+}
+
+/// url to download from and the path of the partial download, the
+/// extension is a guess that is corrected once the download finishes
+fn part_path(id: EpisodeKey, episode: &EpisodeExt) -> Option<(reqwest::Url, PathBuf)> {
+    let url = reqwest::Url::parse(&episode.stream_url).ok()?;
+    let file_type = episode
+        .mime_type
+        .as_deref()
+        .and_then(FileType::from_mime)
+        .or_else(|| FileType::from_url(&url))
+        .unwrap_or(FileType::Mp3);
+    let mut path = base_file_path(id);
+    path.set_extension(&format!("{}.part", file_type.as_str()));
+    Some((url, path))
+}
+
+/// where a stream is cached while it plays, the stream becomes a
+/// download once the entire episode was fetched
+pub fn stream_path(id: EpisodeKey, episode: &EpisodeExt) -> Option<PathBuf> {
+    let (_, mut path) = part_path(id, episode)?;
+    path.set_extension("stream"); // replaces the .part
+    Some(path)
+}
+
+/// turn a completely fetched stream (see: `stream_path`) into a download
+pub async fn promote_stream(
+    path: PathBuf,
+    total: u64,
+    content_type: Option<FileType>,
+) -> Result<(), Error> {
+    subscribe::finish(path, Some(total), content_type).await
+}
+
+/// partial downloads not in the queue were started before the queue was
+/// stored, returns those whose episode still exists. The others are
+/// removed as are stream caches left behind when the app closed.
+pub async fn orphaned_parts(pod_db: database::PodcastDb) -> Vec<EpisodeKey> {
+    let scan = move || {
+        let podcasts = match pod_db.get_podcasts() {
+            Ok(podcasts) => podcasts,
+            Err(e) => {
+                log::error!("could not look for unfinished downloads: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut orphaned = Vec::new();
+        for (podcast_id, _) in podcasts {
+            for stale in cache_files(podcast_id, "stream") {
+                // which parts of the episode were fetched is lost
+                log::info!("removing stale stream cache: {:?}", stale);
+                if let Err(e) = std::fs::remove_file(&stale) {
+                    log::error!("could not remove {:?}: {}", stale, e);
+                }
+            }
+            for part in cache_files(podcast_id, "part") {
+                // files are named <episode key>.<extension>.part
+                let key = part
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.split('.').next())
+                    .and_then(|k| k.parse::<EpisodeKey>().ok())
+                    .filter(|k| pod_db.get_episode_ext(*k).is_ok());
+                match key {
+                    Some(key) => orphaned.push(key),
+                    None => {
+                        log::info!("removing orphaned partial download: {:?}", part);
+                        if let Err(e) = std::fs::remove_file(&part) {
+                            log::error!("could not remove {:?}: {}", part, e);
+                        }
+                    }
+                }
+            }
+        }
+        orphaned
+    };
+    tokio::task::spawn_blocking(scan).await.unwrap_or_else(|e| {
+        log::error!("could not look for unfinished downloads: {}", e);
+        Vec::new()
+    })
+}
+
+/// partial downloads (.part) or stream caches (.stream) of a podcast
+fn cache_files(podcast_id: PodcastKey, extension: &str) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(podcast_dir(podcast_id)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|e| e == extension).unwrap_or(false))
+        .collect()
+}
+
+/// where downloads are stored, none if the user has no download folder
+fn user_download_dir() -> Option<PathBuf> {
     use directories::UserDirs;
-    let user_dirs = UserDirs::new().expect("can not download if the user has no home directory");
-    let mut dl_dir = user_dirs
-        .download_dir()
-        .expect("need a download folder to be able to download")
-        .to_owned();
-    dl_dir.push(env!("CARGO_BIN_NAME"));
-    dl_dir.push(&episode.podcast);
-    dl_dir.push(&episode.title);
-    dl_dir
+    let user_dirs = UserDirs::new()?;
+    let dl_dir = user_dirs.download_dir()?;
+    Some(dl_dir.join(env!("CARGO_BIN_NAME")))
+}
+
+fn download_dir() -> PathBuf {
+    user_download_dir().expect("need a download folder to be able to download")
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum FileType {
-    Mp3,
+/// directory with the downloaded episodes of a podcast, named after
+/// the podcast key so it does not change when the podcast is renamed
+pub fn podcast_dir(podcast_id: PodcastKey) -> PathBuf {
+    let mut dir = download_dir();
+    dir.push(podcast_id.to_string());
+    dir
 }
 
-impl FileType {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            FileType::Mp3 => "mp3",
+/// removes a downloaded episode and any partial download or stream
+/// cache next to it, takes the path without extension (see: `base_file_path`)
+pub async fn remove_file(base_path: PathBuf, file_type: FileType) {
+    let mut path = base_path.clone();
+    path.set_extension(file_type.as_str());
+    let mut part = base_path.clone();
+    part.set_extension(format!("{}.part", file_type.as_str()));
+    let mut stream = base_path;
+    stream.set_extension(format!("{}.stream", file_type.as_str()));
+
+    for path in &[path, part, stream] {
+        match tokio::fs::remove_file(path).await {
+            Ok(_) => log::info!("removed: {:?}", path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => log::error!("could not remove {:?}: {}", path, e),
         }
     }
 }
 
-pub fn hash(string: &str) -> u64 {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::Hash;
-    use std::hash::Hasher;
+async fn remove_part(path: PathBuf) {
+    match tokio::fs::remove_file(&path).await {
+        Ok(_) => log::info!("removed: {:?}", path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => log::error!("could not remove {:?}: {}", path, e),
+    }
+}
+
+/// removes all downloaded episodes of a podcast
+pub async fn remove_podcast_dir(podcast_id: PodcastKey) {
+    let dir = podcast_dir(podcast_id);
+    match tokio::fs::remove_dir_all(&dir).await {
+        Ok(_) => log::info!("removed downloads in: {:?}", dir),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => log::error!("could not remove downloads in {:?}: {}", dir, e),
+    }
+}
 
-    let mut hasher = DefaultHasher::new();
-    string.hash(&mut hasher);
-    hasher.finish()
+/// the directory with downloads of a podcast if anything was downloaded
+pub fn existing_podcast_dir(podcast_id: PodcastKey) -> Option<PathBuf> {
+    let dir = user_download_dir()?.join(podcast_id.to_string());
+    Some(dir).filter(|dir| dir.is_dir())
 }
 
-pub async fn scan_podcast_dir(podcast: impl AsRef<str>) -> HashMap<u64, FileType> {
-    use directories::UserDirs;
+/// before downloads were stored by podcast key they were stored by title
+pub fn move_legacy_dir(title: &str, podcast_id: PodcastKey) {
+    let dl_dir = match user_download_dir() {
+        Some(dir) => dir,
+        None => return, // nothing can have been downloaded
+    };
+    let legacy = dl_dir.join(title);
+    if !legacy.is_dir() {
+        return;
+    }
+    let dir = dl_dir.join(podcast_id.to_string());
+    match std::fs::rename(&legacy, &dir) {
+        Ok(_) => log::info!("moved downloads from: {:?} to: {:?}", legacy, dir),
+        Err(e) => log::error!("could not move downloads from {:?}: {}", legacy, e),
+    }
+}
+
+/// path to file without any extension, named after the episode key
+/// so it does not change when the episode is renamed
+pub fn base_file_path(id: EpisodeKey) -> PathBuf {
+    let mut path = podcast_dir(id.podcast());
+    path.push(id.to_string());
+    path
+}
+
+/// moves the download and any partial download named `from` (without
+/// extension) to where the episode with key `to` is stored
+pub fn move_downloads(from: &str, to: EpisodeKey) {
+    let dir = match existing_podcast_dir(to.podcast()) {
+        Some(dir) => dir,
+        None => return, // nothing downloaded
+    };
+    move_files(&dir, from, &to.to_string());
+}
+
+/// titles can contain dots, the extension is appended instead of
+/// replacing whatever follows the last dot
+fn move_files(dir: &Path, from: &str, to: &str) {
+    for file_type in FileType::ALL.iter() {
+        for extension in &[
+            file_type.as_str().to_owned(),
+            format!("{}.part", file_type.as_str()),
+        ] {
+            let from = dir.join(format!("{}.{}", from, extension));
+            if !from.is_file() {
+                continue;
+            }
+            let to = dir.join(format!("{}.{}", to, extension));
+            match std::fs::rename(&from, &to) {
+                Ok(_) => log::info!("renamed download: {:?} to: {:?}", from, to),
+                Err(e) => log::error!("could not rename download {:?}: {}", from, e),
+            }
+        }
+    }
+}
+
+pub async fn scan_podcast_dir(podcast_id: PodcastKey) -> HashMap<EpisodeKey, FileType> {
     use tokio::fs;
 
-    let user_dirs = UserDirs::new().expect("can not download if the user has no home directory");
-    let mut dir = user_dirs
-        .download_dir()
-        .expect("need a download folder to be able to download")
-        .to_owned();
-    dir.push(env!("CARGO_BIN_NAME"));
-    dir.push(podcast.as_ref());
+    let dir = podcast_dir(podcast_id);
 
     let mut set = HashMap::new();
     let entries = fs::read_dir(dir).await;
@@ -107,14 +437,39 @@ pub async fn scan_podcast_dir(podcast: impl AsRef<str>) -> HashMap<u64, FileType
     let mut entries = entries.unwrap();
 
     while let Some(entry) = entries.next_entry().await.unwrap() {
-        let relative_path = entry.file_name();
-        let relative_path = relative_path.to_str().unwrap();
-        if let Some(name) = relative_path.strip_suffix(".mp3")
-        // .or_else(relative_path.strip_suffix(".other")
-        {
-            log::trace!("found on disk episode: \"{}\"", name);
-            set.insert(hash(name), FileType::Mp3);
+        let path = entry.path();
+        let file_type = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(FileType::from_extension);
+        let key = path
+            .file_stem()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.parse().ok());
+        if let (Some(file_type), Some(key)) = (file_type, key) {
+            log::trace!("found on disk episode: {}", key);
+            set.insert(key, file_type);
         }
     }
     set
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn move_dotted_title() {
+        let dir = std::env::temp_dir().join(format!("pods_download_{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("Ep. 12 Foo.mp3"), b"done").unwrap();
+        std::fs::write(dir.join("Ep. 13 Bar.ogg.part"), b"partial").unwrap();
+
+        move_files(&dir, "Ep. 12 Foo", "1_12");
+        move_files(&dir, "Ep. 13 Bar", "1_13");
+
+        let moved = dir.join("1_12.mp3").is_file() && dir.join("1_13.ogg.part").is_file();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(moved);
+    }
+}
@@ -0,0 +1,127 @@
+/// audio formats podcasts are published in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Mp3,
+    /// aac in an mp4 container
+    M4a,
+    /// vorbis in an ogg container
+    Ogg,
+    /// opus in an ogg container
+    Opus,
+    Flac,
+    Wav,
+}
+
+impl FileType {
+    pub const ALL: [FileType; 6] = [
+        FileType::Mp3,
+        FileType::M4a,
+        FileType::Ogg,
+        FileType::Opus,
+        FileType::Flac,
+        FileType::Wav,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Mp3 => "mp3",
+            FileType::M4a => "m4a",
+            FileType::Ogg => "ogg",
+            FileType::Opus => "opus",
+            FileType::Flac => "flac",
+            FileType::Wav => "wav",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let file_type = match extension.to_ascii_lowercase().as_str() {
+            "mp3" => FileType::Mp3,
+            "m4a" | "mp4" | "aac" => FileType::M4a,
+            "ogg" | "oga" => FileType::Ogg,
+            "opus" => FileType::Opus,
+            "flac" => FileType::Flac,
+            "wav" => FileType::Wav,
+            _ => return None,
+        };
+        Some(file_type)
+    }
+
+    /// from a media type such as the enclosure type or a `Content-Type`
+    /// header, `audio/ogg` is assumed to hold vorbis
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.split(';').next()?.trim().to_ascii_lowercase();
+        let file_type = match mime.as_str() {
+            "audio/mpeg" | "audio/mp3" | "audio/mpeg3" | "audio/x-mpeg" => FileType::Mp3,
+            "audio/mp4" | "audio/x-m4a" | "audio/m4a" | "audio/aac" | "audio/aacp" => FileType::M4a,
+            "audio/ogg" | "audio/vorbis" | "application/ogg" => FileType::Ogg,
+            "audio/opus" => FileType::Opus,
+            "audio/flac" | "audio/x-flac" => FileType::Flac,
+            "audio/wav" | "audio/x-wav" | "audio/wave" | "audio/vnd.wave" => FileType::Wav,
+            _ => return None,
+        };
+        Some(file_type)
+    }
+
+    /// from the first bytes of the file, needs at least 36 bytes to
+    /// tell ogg vorbis and ogg opus apart
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        let frame_sync = bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] & 0xE0 == 0xE0;
+        let file_type = if bytes.starts_with(b"ID3") || frame_sync {
+            FileType::Mp3
+        } else if bytes.get(4..8) == Some(&b"ftyp"[..]) {
+            FileType::M4a
+        } else if bytes.starts_with(b"OggS") {
+            if bytes.get(28..36) == Some(&b"OpusHead"[..]) {
+                FileType::Opus
+            } else {
+                FileType::Ogg
+            }
+        } else if bytes.starts_with(b"fLaC") {
+            FileType::Flac
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WAVE"[..]) {
+            FileType::Wav
+        } else {
+            return None;
+        };
+        Some(file_type)
+    }
+
+    /// the extension of the last path segment of an url
+    pub fn from_url(url: &reqwest::Url) -> Option<Self> {
+        let name = url.path_segments()?.next_back()?;
+        let (_, extension) = name.rsplit_once('.')?;
+        Self::from_extension(extension)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_from_mime_and_url() {
+        assert_eq!(FileType::from_mime("audio/mpeg"), Some(FileType::Mp3));
+        assert_eq!(
+            FileType::from_mime("audio/x-m4a; charset=binary"),
+            Some(FileType::M4a)
+        );
+        assert_eq!(FileType::from_mime("text/html"), None);
+        let url = reqwest::Url::parse("https://example.org/ep1.opus?token=x").unwrap();
+        assert_eq!(FileType::from_url(&url), Some(FileType::Opus));
+    }
+
+    #[test]
+    fn detect_from_magic() {
+        let mut opus = b"OggS".to_vec();
+        opus.resize(28, 0);
+        opus.extend_from_slice(b"OpusHead");
+        assert_eq!(FileType::from_magic(&opus), Some(FileType::Opus));
+        assert_eq!(FileType::from_magic(b"OggS\0\0\0"), Some(FileType::Ogg));
+        assert_eq!(
+            FileType::from_magic(b"\0\0\0\x20ftypM4A "),
+            Some(FileType::M4a)
+        );
+        assert_eq!(FileType::from_magic(b"ID3\x04"), Some(FileType::Mp3));
+        assert_eq!(FileType::from_magic(b"<html>"), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! chapters embedded in mp3 files as ID3v2 `CHAP` frames, see:
+//! https://id3.org/id3v2-chapters-1.0
+
+use crate::database::Chapter;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const HEADER: usize = 10;
+const UNSYNCHRONISATION: u8 = 0x80;
+const EXTENDED_HEADER: u8 = 0x40;
+/// the `CTOC` frame is the root of the table of contents
+const TOP_LEVEL: u8 = 0x02;
+
+struct Frame<'a> {
+    id: &'a [u8],
+    body: &'a [u8],
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, b| (size << 7) | (*b as usize & 0x7f))
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// frames in data, stops at the padding or a malformed frame
+fn frames(mut data: &[u8], version: u8) -> Vec<Frame<'_>> {
+    let mut frames = Vec::new();
+    while data.len() >= HEADER && data[0] != 0 {
+        let size = match version {
+            4 => syncsafe(&data[4..8]),
+            _ => be_u32(&data[4..8]) as usize,
+        };
+        if data.len() < HEADER + size {
+            break;
+        }
+        frames.push(Frame {
+            id: &data[..4],
+            body: &data[HEADER..HEADER + size],
+        });
+        data = &data[HEADER + size..];
+    }
+    frames
+}
+
+/// splits of a null terminated string
+fn terminated(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|b| *b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+/// the content of a text frame
+fn text(body: &[u8]) -> Option<String> {
+    let (encoding, text) = body.split_first()?;
+    let text = match encoding {
+        0 => text
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect(),
+        1 | 2 => {
+            let mut units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            if units.first() == Some(&0xFFFE) {
+                // little endian byte order mark
+                units.iter_mut().for_each(|u| *u = u.swap_bytes());
+            }
+            if units.first() == Some(&0xFEFF) {
+                units.remove(0);
+            }
+            let end = units.iter().position(|u| *u == 0).unwrap_or(units.len());
+            String::from_utf16_lossy(&units[..end])
+        }
+        3 => {
+            let end = text.iter().position(|b| *b == 0).unwrap_or(text.len());
+            String::from_utf8_lossy(&text[..end]).into_owned()
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// the element id and chapter in a `CHAP` frame
+fn chap(body: &[u8], version: u8) -> Option<(&[u8], Chapter)> {
+    let (id, rest) = terminated(body)?;
+    if rest.len() < 16 {
+        return None;
+    }
+    let start_ms = be_u32(&rest[..4]);
+    let title = frames(&rest[16..], version)
+        .iter()
+        .find(|f| f.id == b"TIT2")
+        .and_then(|f| text(f.body))
+        .unwrap_or_default();
+    let chapter = Chapter {
+        start: start_ms as f32 / 1000.,
+        title,
+    };
+    Some((id, chapter))
+}
+
+/// whether a `CTOC` frame is the top level one and its children
+fn ctoc(body: &[u8]) -> Option<(bool, Vec<&[u8]>)> {
+    let (_, rest) = terminated(body)?;
+    let (flags, rest) = rest.split_first()?;
+    let (count, mut rest) = rest.split_first()?;
+    let mut children = Vec::new();
+    for _ in 0..*count {
+        let (child, next) = terminated(rest)?;
+        children.push(child);
+        rest = next;
+    }
+    Some((flags & TOP_LEVEL != 0, children))
+}
+
+/// chapters in an ID3v2.3 or 2.4 tag sorted by start, if there is a
+/// top level table of contents only the chapters it lists
+pub fn parse(tag: &[u8]) -> Vec<Chapter> {
+    if tag.len() < HEADER || &tag[..3] != b"ID3" {
+        return Vec::new();
+    }
+    let version = tag[3];
+    let flags = tag[5];
+    if version != 3 && version != 4 {
+        return Vec::new(); // ID3v2.2 has no chapters
+    }
+    if flags & UNSYNCHRONISATION != 0 {
+        log::warn!("unsynchronised ID3 tags are not supported");
+        return Vec::new();
+    }
+
+    let size = syncsafe(&tag[6..10]);
+    let mut data = &tag[HEADER..tag.len().min(HEADER + size)];
+    if flags & EXTENDED_HEADER != 0 && data.len() >= 4 {
+        let extended = match version {
+            4 => syncsafe(&data[..4]),
+            _ => 4 + be_u32(&data[..4]) as usize,
+        };
+        data = &data[extended.min(data.len())..];
+    }
+
+    let frames = frames(data, version);
+    let mut chapters: Vec<_> = frames
+        .iter()
+        .filter(|f| f.id == b"CHAP")
+        .filter_map(|f| chap(f.body, version))
+        .collect();
+    let toc = frames
+        .iter()
+        .filter(|f| f.id == b"CTOC")
+        .filter_map(|f| ctoc(f.body))
+        .find(|(top_level, _)| *top_level);
+    if let Some((_, children)) = toc {
+        chapters.retain(|(id, _)| children.contains(id));
+    }
+
+    let mut chapters: Vec<_> = chapters.into_iter().map(|(_, c)| c).collect();
+    chapters.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    chapters
+}
+
+/// the chapters in the ID3 tag at the start of an mp3 file
+pub fn read_chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; HEADER];
+    if file.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(Vec::new());
+    }
+    let size = syncsafe(&header[6..10]);
+    let mut tag = header.to_vec();
+    file.take(size as u64).read_to_end(&mut tag)?;
+    Ok(parse(&tag))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn chap(id: &str, start_ms: u32, title: &[u8]) -> Vec<u8> {
+        let mut body = id.as_bytes().to_vec();
+        body.push(0);
+        body.extend_from_slice(&start_ms.to_be_bytes());
+        body.extend_from_slice(&(start_ms + 1000).to_be_bytes());
+        body.extend_from_slice(&[0xff; 8]);
+        body.extend(frame(b"TIT2", title));
+        frame(b"CHAP", &body)
+    }
+
+    fn tag(frames: &[Vec<u8>]) -> Vec<u8> {
+        let data: Vec<u8> = frames.concat();
+        let size = data.len() + 20; // some padding
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+        tag.extend(data);
+        tag.extend_from_slice(&[0; 20]);
+        tag
+    }
+
+    #[test]
+    fn chapters_in_toc_order_by_start() {
+        let utf16_le = [1, 0xff, 0xfe, b'O', 0, b'u', 0, b't', 0, b'r', 0, b'o', 0];
+        let tag = tag(&[
+            frame(b"TIT2", b"\x03Episode"),
+            chap("ch1", 61_500, &utf16_le),
+            chap("ch0", 0, b"\x00Intro\x00"),
+            chap("ad", 30_000, b"\x03Sponsor"),
+            frame(b"CTOC", b"toc\x00\x03\x02ch0\x00ch1\x00"),
+        ]);
+
+        let chapters = parse(&tag);
+        let expected = [
+            Chapter {
+                start: 0.,
+                title: "Intro".to_owned(),
+            },
+            Chapter {
+                start: 61.5,
+                title: "Outro".to_owned(),
+            },
+        ];
+        assert_eq!(chapters, expected);
+    }
+}
@@ -1,4 +1,6 @@
-use super::Download;
+use super::{Download, FileType};
+use crate::database::EpisodeKey;
+use crate::retry;
 use error_level::ErrorLevel;
 use iced_futures::futures;
 use std::path::PathBuf;
@@ -9,7 +11,10 @@ use tokio::io::{self, AsyncWriteExt};
 #[derive(Debug, Clone)]
 pub enum Progress {
     Started,
-    Advanced(f32),
+    /// percentage done and the number of bytes downloaded
+    Advanced(f32, u64),
+    /// download failed and will be retried, this is retry attempt n
+    Retrying(u32),
     Finished,
     Error(Error),
 }
@@ -24,15 +29,25 @@ pub enum Error {
     #[error("Could not store download")]
     Io(#[from] Arc<io::Error>),
     #[report(warn)]
-    #[error("Do not know what file type this is (no extension given)")]
-    NoExtension,
+    #[error("Download incomplete, got {0} of {1} bytes")]
+    Incomplete(u64, u64),
+}
+
+impl Error {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Error::Download(e) => retry::is_retryable(e),
+            Error::Incomplete(..) => true,
+            Error::Io(_) => false,
+        }
+    }
 }
 
 impl<H, I> iced_futures::subscription::Recipe<H, I> for Download
 where
     H: std::hash::Hasher,
 {
-    type Output = Progress;
+    type Output = (EpisodeKey, Progress);
 
     fn hash(&self, state: &mut H) {
         use std::hash::Hash;
@@ -44,50 +59,181 @@ where
         self: Box<Self>,
         _input: futures::stream::BoxStream<'static, I>,
     ) -> futures::stream::BoxStream<'static, Self::Output> {
-        Box::pin(futures::stream::unfold(
-            State::Start(self.url, self.path),
-            |state| async move { stream_state_machine(state).await },
-        ))
+        use futures::StreamExt;
+        let key = self.key;
+        let stream =
+            futures::stream::unfold(State::Start(self.url, self.path, 0), |state| async move {
+                stream_state_machine(state).await
+            });
+        Box::pin(stream.map(move |progress| (key, progress)))
     }
 }
 
 type StateResult = Option<(Progress, State)>;
 async fn stream_state_machine(current: State) -> StateResult {
     match current {
-        State::Start(url, path) => start(url, path)
-            .await
-            .unwrap_or_else(|e| Some((Progress::Error(e), State::Errored))),
-        State::Downloading(data) => downloading(data)
+        State::Start(url, path, attempt) => start(url.clone(), path.clone(), attempt)
             .await
-            .unwrap_or_else(|e| Some((Progress::Error(e), State::Errored))),
-        State::Finished(temp_path) => {
-            let mut path = temp_path.clone(); // name.extension.part
-            path.set_extension(""); // this removes the .part
-            fs::rename(temp_path, path).await.unwrap();
-            None
+            .unwrap_or_else(|e| on_error(e, url, path, attempt)),
+        State::Downloading(data) => {
+            let (url, path, attempt) = (data.url.clone(), data.path.clone(), data.attempt);
+            downloading(data)
+                .await
+                .unwrap_or_else(|e| on_error(e, url, path, attempt))
         }
+        State::Retry(url, path, attempt) => {
+            tokio::time::sleep(retry::Policy::default().delay(attempt - 1)).await;
+            start(url.clone(), path.clone(), attempt)
+                .await
+                .unwrap_or_else(|e| on_error(e, url, path, attempt))
+        }
+        State::Finished => None,
         State::Errored => None,
     }
 }
 
-async fn start(url: reqwest::Url, path: PathBuf) -> Result<StateResult> {
-    log::info!("downloading to file: {}", &path.to_string_lossy());
-    let res = reqwest::get(url).await.map_err(Arc::from)?;
-    let total = res.content_length();
+/// retry if the error is transient, a retry resumes from what was
+/// already downloaded
+fn on_error(e: Error, url: reqwest::Url, path: PathBuf, attempt: u32) -> StateResult {
+    if e.is_retryable() && retry::Policy::default().should_retry(attempt) {
+        log::warn!("download of {} failed, will retry: {}", url, e);
+        let attempt = attempt + 1;
+        Some((
+            Progress::Retrying(attempt),
+            State::Retry(url, path, attempt),
+        ))
+    } else {
+        Some((Progress::Error(e), State::Errored))
+    }
+}
+
+fn content_range(res: &reqwest::Response) -> Option<&str> {
+    use reqwest::header::CONTENT_RANGE;
+    res.headers().get(CONTENT_RANGE)?.to_str().ok()
+}
+
+/// total size of the file from a `Content-Range: bytes 100-999/1000` header
+fn range_total(res: &reqwest::Response) -> Option<u64> {
+    content_range(res)?.rsplit('/').next()?.parse().ok()
+}
+
+/// first byte sent from a `Content-Range: bytes 100-999/1000` header
+fn range_start(res: &reqwest::Response) -> Option<u64> {
+    let range = content_range(res)?.strip_prefix("bytes ")?;
+    range.split('-').next()?.parse().ok()
+}
+
+/// requests the download from byte `from` onwards
+async fn send(url: &reqwest::Url, from: u64) -> Result<reqwest::Response> {
+    use reqwest::header::RANGE;
+
+    let mut request = reqwest::Client::new().get(url.clone());
+    if from > 0 {
+        request = request.header(RANGE, format!("bytes={}-", from));
+    }
+    Ok(request.send().await.map_err(Arc::from)?)
+}
+
+/// continues an existing partial download if the server supports
+/// range requests, otherwise starts over
+async fn start(url: reqwest::Url, path: PathBuf, attempt: u32) -> Result<StateResult> {
+    use reqwest::StatusCode;
+
+    let mut existing = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+    let mut res = send(&url, existing).await?;
+    if existing > 0 && res.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        if range_total(&res) == Some(existing) {
+            // the part file already holds the entire download
+            finish(path, Some(existing), None).await?;
+            return Ok(Some((Progress::Finished, State::Finished)));
+        }
+        // the file changed on the server or the part file is corrupt
+        log::warn!(
+            "partial download does not match, starting over: {:?}",
+            &path
+        );
+        existing = 0;
+        res = send(&url, 0).await?;
+    }
+    let mut res = res.error_for_status().map_err(Arc::from)?;
+    if res.status() != StatusCode::PARTIAL_CONTENT {
+        existing = 0; // the server ignored the range and sends everything
+    } else if existing > 0 && range_start(&res) != Some(existing) {
+        log::warn!("server sent the wrong range, starting over: {:?}", &path);
+        existing = 0;
+        res = send(&url, 0).await?.error_for_status().map_err(Arc::from)?;
+    }
+    let content_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .and_then(FileType::from_mime);
+
     let dir = path.parent().unwrap();
     fs::create_dir_all(dir).await.map_err(Arc::from)?;
-    let file = fs::File::create(&path).await.map_err(Arc::from)?;
+    let (file, downloaded, total) = if existing > 0 {
+        log::info!("resuming download at byte {} to: {:?}", existing, &path);
+        let total = range_total(&res).or_else(|| res.content_length().map(|l| l + existing));
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(Arc::from)?;
+        (file, existing, total)
+    } else {
+        log::info!("downloading to file: {}", &path.to_string_lossy());
+        let file = fs::File::create(&path).await.map_err(Arc::from)?;
+        (file, 0, res.content_length())
+    };
+
     let file = io::BufWriter::new(file);
     let state = DownloadData {
         res,
         file,
         total,
-        downloaded: 0,
+        downloaded,
         path,
+        url,
+        attempt,
+        content_type,
     };
     Ok(Some((Progress::Started, State::Downloading(state))))
 }
 
+/// check the download is complete then remove the .part (or .stream)
+/// extension, the extension is corrected if the file turns out to be of
+/// another type
+pub(super) async fn finish(
+    temp_path: PathBuf,
+    total: Option<u64>,
+    content_type: Option<FileType>,
+) -> Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    let len = fs::metadata(&temp_path).await.map_err(Arc::from)?.len();
+    if let Some(total) = total {
+        if len != total {
+            // keep the .part file so the download can be resumed
+            return Err(Error::Incomplete(len, total));
+        }
+    }
+
+    let mut magic = Vec::with_capacity(36);
+    let file = fs::File::open(&temp_path).await.map_err(Arc::from)?;
+    file.take(36)
+        .read_to_end(&mut magic)
+        .await
+        .map_err(Arc::from)?;
+
+    let mut path = temp_path.clone(); // name.extension.part
+    path.set_extension(""); // this removes the .part or .stream
+    if let Some(file_type) = FileType::from_magic(&magic).or(content_type) {
+        path.set_extension(file_type.as_str());
+    }
+    fs::rename(temp_path, path).await.map_err(Arc::from)?;
+    Ok(())
+}
+
 async fn downloading(data: DownloadData) -> Result<StateResult> {
     let DownloadData {
         mut res,
@@ -95,9 +241,24 @@ async fn downloading(data: DownloadData) -> Result<StateResult> {
         total,
         mut downloaded,
         path,
+        url,
+        attempt,
+        content_type,
     } = data;
-    match res.chunk().await.map_err(Arc::from)? {
-        None => Ok(Some((Progress::Finished, State::Finished(path)))),
+    let chunk = match res.chunk().await {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            // make sure everything received is on disk before resuming
+            file.flush().await.map_err(Arc::from)?;
+            return Err(Error::Download(Arc::new(e)));
+        }
+    };
+    match chunk {
+        None => {
+            file.flush().await.map_err(Arc::from)?;
+            finish(path, total, content_type).await?;
+            Ok(Some((Progress::Finished, State::Finished)))
+        }
         Some(chunk) => {
             downloaded += chunk.len() as u64;
             file.write_all(&chunk).await.map_err(Arc::from)?;
@@ -105,13 +266,16 @@ async fn downloading(data: DownloadData) -> Result<StateResult> {
             let percentage = total
                 .map(|t| 100.0 * downloaded as f32 / t as f32)
                 .unwrap_or(0.0);
-            let progress = Progress::Advanced(percentage);
+            let progress = Progress::Advanced(percentage, downloaded);
             let data = DownloadData {
                 res,
                 file,
                 total,
                 downloaded,
                 path,
+                url,
+                attempt,
+                content_type,
             };
             Ok(Some((progress, State::Downloading(data))))
         }
@@ -125,13 +289,19 @@ pub struct DownloadData {
     total: Option<u64>,
     downloaded: u64,
     path: PathBuf,
+    url: reqwest::Url,
+    attempt: u32,
+    /// file type according to the server
+    content_type: Option<FileType>,
 }
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum State {
-    Start(reqwest::Url, PathBuf),
+    Start(reqwest::Url, PathBuf, u32),
     Downloading(DownloadData), //keep unboxed
-    Finished(PathBuf),
+    /// wait before retrying, holds the attempt number
+    Retry(reqwest::Url, PathBuf, u32),
+    Finished,
     Errored,
 }
@@ -0,0 +1,107 @@
+//! Podcasting 2.0 json chapters, see:
+//! https://github.com/Podcastindex-org/podcast-namespace/blob/main/chapters/jsonChapters.md
+
+use crate::database::{Chapter, PodcastDb, PodcastKey};
+use crate::retry;
+use eyre::WrapErr;
+use serde::Deserialize;
+
+/// chapters fetched per refresh, the others follow on the next refreshes
+const MAX_FETCHES: usize = 20;
+
+#[derive(Deserialize)]
+struct JsonChapters {
+    #[serde(default)]
+    chapters: Vec<JsonChapter>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonChapter {
+    start_time: f32,
+    #[serde(default)]
+    title: String,
+    /// false for chapters that should not be listed, like ad markers
+    toc: Option<bool>,
+}
+
+/// the listed chapters sorted by start
+pub fn parse(body: &str) -> Result<Vec<Chapter>, serde_json::Error> {
+    let json: JsonChapters = serde_json::from_str(body)?;
+    let mut chapters: Vec<_> = json
+        .chapters
+        .into_iter()
+        .filter(|c| c.toc.unwrap_or(true))
+        .map(|c| Chapter {
+            start: c.start_time,
+            title: c.title,
+        })
+        .collect();
+    chapters.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    Ok(chapters)
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> eyre::Result<Vec<Chapter>> {
+    let send = || {
+        let request = client.get(url);
+        async move { request.send().await?.error_for_status() }
+    };
+    let res = retry::retry(&retry::Policy::default(), send)
+        .await
+        .wrap_err("could not fetch chapters")?;
+    let body = res.text().await.wrap_err("could not download chapters")?;
+    parse(&body).wrap_err("could not parse chapters")
+}
+
+/// fetch and store the chapters of episodes whose chapters url was not
+/// fetched yet, newest episodes first. This runs on every refresh, also
+/// if the feed did not change. A url that lists no chapters or can not be
+/// fetched is not tried again, unless the network or server was down.
+pub async fn fetch_missing(pod_db: &PodcastDb, podcast_id: PodcastKey) {
+    let missing = match pod_db.missing_chapters(podcast_id) {
+        Ok(missing) => missing,
+        Err(e) => return log::error!("could not find episodes missing chapters: {}", e),
+    };
+    let client = reqwest::Client::new();
+    for (key, url) in missing.into_iter().take(MAX_FETCHES) {
+        let chapters = match fetch(&client, &url).await {
+            Ok(chapters) => chapters,
+            Err(e) if e.downcast_ref().map_or(false, retry::is_retryable) => {
+                log::warn!(
+                    "could not fetch chapters from {}, trying again later: {:#}",
+                    url,
+                    e
+                );
+                continue;
+            }
+            Err(e) => {
+                log::warn!("no chapters from {}: {:#}", url, e);
+                Vec::new()
+            }
+        };
+        if let Err(e) = pod_db.set_fetched_chapters(key, chapters) {
+            log::error!("could not store chapters: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_hidden_chapters() {
+        let body = r#"{
+            "version": "1.2.0",
+            "chapters": [
+                { "startTime": 95.5, "title": "Interview" },
+                { "startTime": 0, "title": "Intro", "img": "https://example.org/intro.jpg" },
+                { "startTime": 60, "title": "Sponsor", "toc": false }
+            ]
+        }"#;
+        let chapters = parse(body).unwrap();
+        let titles: Vec<_> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Intro", "Interview"]);
+        assert_eq!(chapters[1].start, 95.5);
+    }
+}
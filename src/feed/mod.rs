@@ -1,10 +1,15 @@
 use eyre::WrapErr;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use url::Url;
 
+mod chapters;
+pub mod opml;
+mod parse;
 mod search;
 use crate::database;
-use crate::database::{Date, EpisodeExt, Podcast, PodcastKey};
+use crate::database::{CacheHeaders, Podcast, PodcastKey};
+use crate::retry;
 pub use search::{Search, SearchResult};
 
 pub fn valid_url(s: &str) -> bool {
@@ -15,111 +20,346 @@ pub fn valid_url(s: &str) -> bool {
     }
 }
 
-async fn get_podcast_info(url: &str) -> eyre::Result<rss::Channel> {
-    let feed_text = reqwest::get(url)
-        .await
-        .wrap_err("could not connect to podcast feed")?
-        .error_for_status()
-        .wrap_err("feed server returned error")?
-        .text()
-        .await
-        .wrap_err("could not download body")?;
+/// result of refreshing a single feed
+#[derive(Debug, Clone)]
+pub enum Refreshed {
+    /// server replied the feed did not change since the last refresh
+    Unchanged,
+    Updated,
+    Failed(String),
+}
 
-    let channel = rss::Channel::from_str(&feed_text)
-        .wrap_err_with(|| format!("can not parse feed body as rss, text: {}", url))?;
-    Ok(channel)
+enum Fetched {
+    Unchanged,
+    Updated(parse::Feed, CacheHeaders),
 }
 
-fn get_episode_info(items: &[rss::Item], podcast_title: &str) -> Result<Vec<EpisodeExt>, Error> {
-    let list: Result<Vec<EpisodeExt>, _> = items
-        .iter()
-        .map(|i| to_episode_ext(i, podcast_title))
-        .collect();
-    list
+fn cache_headers(headers: &reqwest::header::HeaderMap) -> CacheHeaders {
+    use reqwest::header::{HeaderName, ETAG, LAST_MODIFIED};
+
+    let get = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+    };
+    CacheHeaders {
+        etag: get(ETAG),
+        last_modified: get(LAST_MODIFIED),
+    }
 }
 
-pub async fn add_podcast(pod_db: database::PodcastDb, url: String) -> (String, PodcastKey) {
-    let info = get_podcast_info(&url).await.unwrap();
+/// redirects followed while fetching a feed
+#[derive(Default)]
+struct Redirects {
+    followed: AtomicBool,
+    temporary: AtomicBool,
+}
 
-    let podcast = Podcast::from_url(&info, url);
-    pod_db.add_podcast(&podcast).unwrap();
+impl Redirects {
+    fn reset(&self) {
+        self.followed.store(false, Ordering::Relaxed);
+        self.temporary.store(false, Ordering::Relaxed);
+    }
+    /// the feed was redirected and every redirect was permanent
+    fn moved(&self) -> bool {
+        self.followed.load(Ordering::Relaxed) && !self.temporary.load(Ordering::Relaxed)
+    }
+}
 
-    let episodes = get_episode_info(info.items(), &podcast.title).unwrap();
-    pod_db
-        .update_episodes(podcast.title.as_str(), episodes)
-        .unwrap();
+/// follows redirects, recording whether any of them is not permanent
+fn redirect_policy(redirects: Arc<Redirects>) -> reqwest::redirect::Policy {
+    use reqwest::StatusCode;
 
-    (podcast.title.clone(), PodcastKey::from(podcast.title))
+    reqwest::redirect::Policy::custom(move |attempt| {
+        redirects.followed.store(true, Ordering::Relaxed);
+        match attempt.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT => (),
+            _ => redirects.temporary.store(true, Ordering::Relaxed),
+        }
+        if attempt.previous().len() > 10 {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    })
 }
 
-fn url_from_extensions(item: &rss::Item) -> Option<String> {
-    let media = item.extensions().get("media")?;
-    let content = media.get("content")?;
-    let extention = content.first()?;
-    if extention.name() != "media:content" {
-        return None;
+/// also returns the url the feed moved to if the server
+/// only replied with permanent redirects
+async fn get_podcast_info(
+    url: &str,
+    cached: Option<&CacheHeaders>,
+) -> eyre::Result<(Fetched, Option<String>)> {
+    use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+    use reqwest::StatusCode;
+
+    let redirects = Arc::new(Redirects::default());
+    let client = reqwest::Client::builder()
+        .redirect(redirect_policy(redirects.clone()))
+        .build()
+        .wrap_err("could not construct http client")?;
+    let mut request = client.get(url);
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
     }
-    extention.attrs().get("url").cloned()
+
+    let send = || {
+        redirects.reset();
+        let request = request.try_clone().expect("feed request has no body");
+        async move { request.send().await?.error_for_status() }
+    };
+    let res = retry::retry(&retry::Policy::default(), send)
+        .await
+        .wrap_err("could not fetch podcast feed")?;
+    // comparing urls does not work, reqwest normalizes them
+    let moved_to = if redirects.moved() {
+        Some(res.url().to_string())
+    } else {
+        None
+    };
+    if res.status() == StatusCode::NOT_MODIFIED {
+        return Ok((Fetched::Unchanged, moved_to));
+    }
+
+    let headers = cache_headers(res.headers());
+    let feed_text = res.text().await.wrap_err("could not download body")?;
+
+    let feed = parse::parse(&feed_text, url.to_owned())
+        .wrap_err_with(|| format!("can not parse feed: {}", url))?;
+    Ok((Fetched::Updated(feed, headers), moved_to))
+}
+
+pub async fn try_add_podcast(
+    pod_db: database::PodcastDb,
+    url: String,
+) -> eyre::Result<(String, PodcastKey)> {
+    let (feed, headers, moved_to) = match get_podcast_info(&url, None).await? {
+        (Fetched::Updated(feed, headers), moved_to) => (feed, headers, moved_to),
+        // nothing was cached so a conforming server never answers this
+        (Fetched::Unchanged, _) => {
+            eyre::bail!("server said the feed was not modified: {}", url)
+        }
+    };
+
+    let parse::Feed {
+        mut podcast,
+        episodes,
+        image,
+        ..
+    } = feed;
+    // the key stays based on the url the user subscribed with
+    let podcast_id = pod_db
+        .add_podcast(&podcast)
+        .wrap_err("could not store podcast")?;
+    if let Some(new_url) = moved_to {
+        log::info!("feed moved from: {} to: {}", podcast.url, new_url);
+        podcast.url = new_url;
+        pod_db
+            .update_podcast(podcast_id, &podcast)
+            .wrap_err("could not store podcast")?;
+    }
+    pod_db
+        .set_cache_headers(podcast_id, &headers)
+        .wrap_err("could not store feed cache headers")?;
+    pod_db
+        .set_artwork(podcast_id, image.as_deref())
+        .wrap_err("could not store podcast artwork")?;
+    pod_db
+        .update_episodes(podcast_id, episodes)
+        .wrap_err("could not store episodes")?;
+    chapters::fetch_missing(&pod_db, podcast_id).await;
+
+    Ok((podcast.title, podcast_id))
+}
+
+pub async fn add_podcast(
+    pod_db: database::PodcastDb,
+    url: String,
+) -> Result<(String, PodcastKey), String> {
+    try_add_podcast(pod_db, url.clone()).await.map_err(|e| {
+        let e = format!("could not add podcast {}: {:#}", url, e);
+        log::warn!("{}", e);
+        e
+    })
 }
 
-fn duration_from_extensions(item: &rss::Item) -> Option<f32> {
-    let media = item.extensions().get("media")?;
-    let content = media.get("content")?;
-    let extention = content.first()?;
-    if extention.name() != "media:content" {
-        return None;
+async fn try_refresh_podcast(
+    pod_db: &database::PodcastDb,
+    podcast_id: PodcastKey,
+    podcast: &Podcast,
+) -> eyre::Result<Refreshed> {
+    let cached = pod_db.get_cache_headers(podcast_id)?;
+    let (fetched, moved_to) = get_podcast_info(&podcast.url, cached.as_ref()).await?;
+    let mut updated = podcast.clone();
+    if let Some(new_url) = moved_to {
+        log::info!(
+            "\"{}\" permanently redirected from: {} to: {}",
+            podcast.title,
+            podcast.url,
+            new_url
+        );
+        updated.url = new_url;
+    }
+
+    let (feed, mut headers) = match fetched {
+        Fetched::Unchanged => {
+            store_podcast_changes(pod_db, podcast_id, podcast, &updated)?;
+            chapters::fetch_missing(pod_db, podcast_id).await;
+            return Ok(Refreshed::Unchanged);
+        }
+        Fetched::Updated(feed, headers) => (feed, Some(headers)),
+    };
+
+    updated.title = feed.podcast.title;
+    if let Some(new_url) = feed.new_url.filter(|u| valid_url(u) && *u != updated.url) {
+        log::info!(
+            "\"{}\" published new feed url, moved from: {} to: {}",
+            updated.title,
+            updated.url,
+            new_url
+        );
+        updated.url = new_url;
+        headers = None; // caching headers of the old host mean nothing to the new one
     }
-    extention
-        .attrs()
-        .get("duration")
-        .map(|u| u.parse().ok())
-        .flatten()
+    store_podcast_changes(pod_db, podcast_id, podcast, &updated)?;
+
+    pod_db
+        .set_artwork(podcast_id, feed.image.as_deref())
+        .wrap_err("could not store podcast artwork")?;
+    pod_db
+        .update_episodes(podcast_id, feed.episodes)
+        .wrap_err("could not store episodes")?;
+    pod_db
+        .set_cache_headers(podcast_id, &headers.unwrap_or_default())
+        .wrap_err("could not store feed cache headers")?;
+    chapters::fetch_missing(pod_db, podcast_id).await;
+    Ok(Refreshed::Updated)
 }
 
-fn parse_itunes_duration(duration: &str) -> Option<f32> {
-    let mut parts = duration.rsplitn(3, ':');
-    let seconds: f32 = parts.next()?.parse().ok()?;
-    let minutes: f32 = parts.next()?.parse().ok()?;
-    let hours: f32 = parts.next().unwrap_or("0").parse().ok()?;
-    let seconds = seconds + 60. * (minutes + 60. * hours);
-    Some(seconds)
+/// the podcast key never changes, episodes and progress stay attached
+/// when the title or feed url changes
+fn store_podcast_changes(
+    pod_db: &database::PodcastDb,
+    podcast_id: PodcastKey,
+    old: &Podcast,
+    new: &Podcast,
+) -> eyre::Result<()> {
+    if old.title != new.title {
+        log::info!(
+            "podcast renamed from: \"{}\" to: \"{}\"",
+            old.title,
+            new.title
+        );
+    }
+    if old.title != new.title || old.url != new.url {
+        pod_db
+            .update_podcast(podcast_id, new)
+            .wrap_err("could not store podcast")?;
+    }
+    Ok(())
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("No stream for podcast episode")]
-    MissingStreamUrl,
-    #[error("No duration for podcast episode")]
-    MissingDuration,
-    #[error("No title for podcast episode")]
-    MissingEpisodeTitle,
+/// fetch new episodes if the feed changed since the last refresh
+pub async fn refresh_podcast(
+    pod_db: &database::PodcastDb,
+    podcast_id: PodcastKey,
+    podcast: &Podcast,
+) -> Refreshed {
+    try_refresh_podcast(pod_db, podcast_id, podcast)
+        .await
+        .unwrap_or_else(|e| Refreshed::Failed(format!("{:#}", e)))
 }
 
-fn to_episode_ext(item: &rss::Item, podcast_title: &str) -> Result<EpisodeExt, Error> {
-    //try to get the url from the description of the media object
-    let stream_url = item.enclosure().map(|encl| encl.url().to_owned());
-
-    //try to get the url and duration possible extensions
-    let stream_url = stream_url.or_else(|| url_from_extensions(item));
-    let duration = duration_from_extensions(item);
-
-    //try to get duration from any included itunes extensions
-    let duration = duration.or_else(|| {
-        item.itunes_ext()
-            .map(|ext| ext.duration().map(parse_itunes_duration).flatten())
-            .flatten()
-    });
-
-    let stream_url = stream_url.ok_or(Error::MissingStreamUrl)?;
-    let duration = duration.ok_or(Error::MissingDuration)?;
-    let title = item.title().ok_or(Error::MissingEpisodeTitle)?;
-    let podcast = podcast_title.to_owned();
-
-    Ok(EpisodeExt {
-        stream_url,
-        duration,
-        title: title.to_owned(),
-        podcast,
-        date: Date::from_item(item),
-    })
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Moving</title>
+    <itunes:new-feed-url>https://example.org/new</itunes:new-feed-url>
+    <item>
+      <title>Episode</title>
+      <enclosure url="https://example.org/1.mp3" type="audio/mpeg" length="1"/>
+      <itunes:duration>10:00</itunes:duration>
+    </item>
+  </channel>
+</rss>"#;
+
+    /// serves the feed, requests to `/redirect/<status>` are redirected
+    /// to `/feed` with that status
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let response = match path.strip_prefix("/redirect/") {
+                    Some(status) => format!(
+                        "HTTP/1.1 {} Redirect\r\nLocation: /feed\r\n\
+                        Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    ),
+                    None => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                        Connection: close\r\n\r\n{}",
+                        FEED.len(),
+                        FEED
+                    ),
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn moved_to(url: &str) -> Option<String> {
+        get_podcast_info(url, None).await.unwrap().1
+    }
+
+    #[tokio::test]
+    async fn only_permanent_redirects_move_the_feed() {
+        let base = serve().await;
+        let feed = format!("{}/feed", base);
+        for status in &[301, 308] {
+            let url = format!("{}/redirect/{}", base, status);
+            assert_eq!(moved_to(&url).await.as_ref(), Some(&feed));
+        }
+        for status in &[302, 307] {
+            let url = format!("{}/redirect/{}", base, status);
+            assert_eq!(moved_to(&url).await, None);
+        }
+        // reqwest adds a trailing slash, that is not a redirect
+        assert_eq!(moved_to(&base).await, None);
+    }
+
+    #[tokio::test]
+    async fn refresh_follows_new_feed_url() {
+        let base = serve().await;
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let pod_db = database::PodcastDb::open(&db).unwrap();
+        let podcast = Podcast {
+            title: "Moving".to_owned(),
+            url: format!("{}/feed", base),
+        };
+        let podcast_id = pod_db.add_podcast(&podcast).unwrap();
+
+        let refreshed = try_refresh_podcast(&pod_db, podcast_id, &podcast).await;
+        assert!(matches!(refreshed, Ok(Refreshed::Updated)));
+        let stored = pod_db.get_podcast(podcast_id).unwrap();
+        assert_eq!(stored.url, "https://example.org/new");
+    }
 }
@@ -0,0 +1,193 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::path::PathBuf;
+
+use crate::database::{self, Podcast, PodcastKey};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("could not parse opml: {0}")]
+    Parse(#[from] quick_xml::Error),
+    #[error("could not read or write opml file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not read podcasts from database: {0}")]
+    Database(#[from] database::Error),
+}
+
+/// A feed found in an opml document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline {
+    pub title: Option<String>,
+    pub url: String,
+}
+
+/// Outcome of importing a single feed from an opml file
+#[derive(Debug, Clone)]
+pub struct ImportResult {
+    pub url: String,
+    /// title and key of the added podcast or the reason it could not be added
+    pub outcome: Result<(String, PodcastKey), String>,
+}
+
+fn to_outline(element: &BytesStart, reader: &Reader<&[u8]>) -> Result<Option<Outline>, Error> {
+    let mut title = None;
+    let mut text = None;
+    let mut url = None;
+    for attr in element.attributes() {
+        let attr = attr?;
+        let value = attr.unescape_and_decode_value(reader)?;
+        match attr.key {
+            b"xmlUrl" => url = Some(value),
+            b"title" => title = Some(value),
+            b"text" => text = Some(value),
+            _ => (),
+        }
+    }
+    Ok(url.map(|url| Outline {
+        title: title.or(text),
+        url,
+    }))
+}
+
+/// all outlines with an `xmlUrl` attribute, nested outlines (categories)
+/// are flattened
+pub fn parse(opml: &str) -> Result<Vec<Outline>, Error> {
+    let mut reader = Reader::from_str(opml);
+    reader.trim_text(true);
+
+    let mut outlines = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.name() == b"outline" => {
+                if let Some(outline) = to_outline(e, &reader)? {
+                    outlines.push(outline);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(outlines)
+}
+
+fn escape(s: &str) -> String {
+    let escaped = quick_xml::escape::escape(s.as_bytes());
+    String::from_utf8_lossy(&escaped).into_owned()
+}
+
+pub fn export<'a>(podcasts: impl IntoIterator<Item = &'a Podcast>) -> String {
+    let mut opml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<opml version="2.0">"#,
+        "\n<head>\n<title>",
+        env!("CARGO_PKG_NAME"),
+        " subscriptions</title>\n</head>\n<body>\n",
+    ));
+    for podcast in podcasts {
+        let title = escape(&podcast.title);
+        let url = escape(&podcast.url);
+        opml.push_str(&format!(
+            r#"<outline type="rss" text="{}" title="{}" xmlUrl="{}"/>"#,
+            title, title, url
+        ));
+        opml.push('\n');
+    }
+    opml.push_str("</body>\n</opml>\n");
+    opml
+}
+
+/// add every feed in the opml file, a feed that fails to import
+/// does not stop the others from being added
+pub async fn import(
+    pod_db: database::PodcastDb,
+    path: PathBuf,
+) -> Result<Vec<ImportResult>, Error> {
+    let text = tokio::fs::read_to_string(&path).await?;
+    let outlines = parse(&text)?;
+
+    let mut report = Vec::new();
+    for Outline { url, .. } in outlines {
+        let outcome = super::try_add_podcast(pod_db.clone(), url.clone())
+            .await
+            .map_err(|e| format!("{:#}", e));
+        if let Err(e) = &outcome {
+            log::warn!("could not import feed {}: {}", url, e);
+        }
+        report.push(ImportResult { url, outcome });
+    }
+    Ok(report)
+}
+
+/// file subscriptions are exported to, lives next to the downloads
+pub fn export_path() -> PathBuf {
+    use directories::UserDirs;
+    let user_dirs = UserDirs::new().expect("can not export if the user has no home directory");
+    let mut path = user_dirs
+        .download_dir()
+        .expect("need a download folder to export to")
+        .to_owned();
+    path.push(env!("CARGO_BIN_NAME"));
+    path.push("subscriptions.opml");
+    path
+}
+
+pub async fn export_to_file(pod_db: database::PodcastDb) -> Result<PathBuf, Error> {
+    let podcasts = pod_db.get_podcasts()?;
+    let path = export_path();
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    tokio::fs::write(&path, export(podcasts.iter().map(|(_, p)| p))).await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OPML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<opml version="2.0">
+  <head><title>exported feeds</title></head>
+  <body>
+    <outline text="Tech">
+      <outline type="rss" text="Soft Skills Engineering" xmlUrl="http://feeds.feedburner.com/SoftSkillsEngineering"/>
+    </outline>
+    <outline type="rss" text="Q &amp; A" title="Q &amp; A show" xmlUrl="https://example.org/feed?a=1&amp;b=2"></outline>
+    <outline text="no feed here"/>
+  </body>
+</opml>"#;
+
+    #[test]
+    fn parse_nested_outlines() {
+        let outlines = parse(OPML).unwrap();
+        assert_eq!(
+            outlines,
+            vec![
+                Outline {
+                    title: Some("Soft Skills Engineering".to_owned()),
+                    url: "http://feeds.feedburner.com/SoftSkillsEngineering".to_owned(),
+                },
+                Outline {
+                    title: Some("Q & A show".to_owned()),
+                    url: "https://example.org/feed?a=1&b=2".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn export_then_parse() {
+        let podcasts = vec![Podcast {
+            title: "Tom & Jerry's <show>".to_owned(),
+            url: "https://example.org/feed?a=1&b=2".to_owned(),
+        }];
+        let outlines = parse(&export(&podcasts)).unwrap();
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(
+            outlines[0].title.as_deref(),
+            Some(podcasts[0].title.as_str())
+        );
+        assert_eq!(outlines[0].url, podcasts[0].url);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::database::{EpisodeExt, Podcast};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+mod atom;
+mod json;
+mod rss;
+
+/// podcast information and episodes parsed from a feed
+/// independent of the format of the feed
+#[derive(Debug)]
+pub struct Feed {
+    pub podcast: Podcast,
+    pub episodes: Vec<EpisodeExt>,
+    /// the publisher moved the feed here (`itunes:new-feed-url`)
+    pub new_url: Option<String>,
+    /// url of the podcast artwork
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Rss,
+    Atom,
+    Json,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Feed is not rss, atom or json feed")]
+    UnknownFormat,
+    #[error("can not parse feed body as rss: {0}")]
+    Rss(#[from] ::rss::Error),
+    #[error("can not parse feed body as atom: {0}")]
+    Atom(#[from] atom_syndication::Error),
+    #[error("can not parse feed body as json feed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("No stream for podcast episode")]
+    MissingStreamUrl,
+    #[error("No duration for podcast episode")]
+    MissingDuration,
+    #[error("No title for podcast episode")]
+    MissingEpisodeTitle,
+}
+
+fn root_element(body: &str) -> Option<Vec<u8>> {
+    let mut reader = Reader::from_str(body);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf).ok()? {
+            Event::Start(e) | Event::Empty(e) => return Some(e.local_name().to_vec()),
+            Event::Eof => return None,
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+/// detect the feed format by looking at the start of the body,
+/// servers often do not set a specific content type for feeds
+pub fn detect(body: &str) -> Option<Format> {
+    let body = body.trim_start();
+    if body.starts_with('{') {
+        return Some(Format::Json);
+    }
+    match root_element(body)?.as_slice() {
+        b"rss" | b"RDF" => Some(Format::Rss),
+        b"feed" => Some(Format::Atom),
+        _ => None,
+    }
+}
+
+pub fn parse(body: &str, url: String) -> Result<Feed, Error> {
+    match detect(body).ok_or(Error::UnknownFormat)? {
+        Format::Rss => self::rss::parse(body, url),
+        Format::Atom => self::atom::parse(body, url),
+        Format::Json => self::json::parse(body, url),
+    }
+}
+
+pub(super) fn parse_itunes_duration(duration: &str) -> Option<f32> {
+    let mut parts = duration.rsplitn(3, ':');
+    let seconds: f32 = parts.next()?.parse().ok()?;
+    let minutes: f32 = parts.next().unwrap_or("0").parse().ok()?;
+    let hours: f32 = parts.next().unwrap_or("0").parse().ok()?;
+    let seconds = seconds + 60. * (minutes + 60. * hours);
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RSS: &str = include_str!("../99percentinvisible");
+    const ATOM: &str = include_str!("parse/fixtures/podcast.atom");
+    const JSON: &str = include_str!("parse/fixtures/podcast.json");
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(detect(RSS), Some(Format::Rss));
+        assert_eq!(detect(ATOM), Some(Format::Atom));
+        assert_eq!(detect(JSON), Some(Format::Json));
+        assert_eq!(detect("<html><body></body></html>"), None);
+    }
+
+    #[test]
+    fn parse_rss() {
+        let feed = parse(RSS, "https://example.org/rss".to_owned()).unwrap();
+        assert_eq!(feed.podcast.title, "99% Invisible");
+        assert_eq!(feed.episodes.len(), 464);
+        let first = &feed.episodes[0];
+        assert_eq!(first.title, "423- Sean Exploder");
+        assert_eq!(first.duration, 31. * 60. + 2.);
+        assert!(first.stream_url.ends_with("423_Sean_Exploder_pt01.mp3"));
+        assert!(feed.image.unwrap().ends_with("99-1400.png"));
+    }
+
+    #[test]
+    fn parse_atom() {
+        let feed = parse(ATOM, "https://example.org/atom".to_owned()).unwrap();
+        assert_eq!(feed.podcast.title, "Atom Example Podcast");
+        assert_eq!(feed.podcast.url, "https://example.org/atom");
+        assert_eq!(feed.image.as_deref(), Some("https://example.org/logo.png"));
+        assert_eq!(feed.episodes.len(), 2);
+        let first = &feed.episodes[0];
+        assert_eq!(first.title, "Episode 2: Enclosures");
+        assert_eq!(first.podcast, "Atom Example Podcast");
+        assert_eq!(first.stream_url, "https://example.org/media/episode2.mp3");
+        assert_eq!(first.mime_type.as_deref(), Some("audio/mpeg"));
+        assert_eq!(first.duration, 3723.);
+        let chapters = first.chapters_url.as_deref();
+        assert_eq!(chapters, Some("https://example.org/media/episode2.json"));
+        assert_eq!(feed.episodes[1].duration, 0.);
+        assert_eq!(feed.episodes[1].chapters_url, None);
+    }
+
+    #[test]
+    fn parse_rss_chapters() {
+        const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:podcast="https://podcastindex.org/namespace/1.0"
+    xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Chapters</title>
+    <item>
+      <title>With chapters</title>
+      <enclosure url="https://example.org/1.mp3" type="audio/mpeg" length="1"/>
+      <itunes:duration>10:00</itunes:duration>
+      <podcast:chapters url="https://example.org/1.json" type="application/json+chapters"/>
+    </item>
+  </channel>
+</rss>"#;
+        let feed = parse(RSS, "https://example.org/rss".to_owned()).unwrap();
+        let chapters = feed.episodes[0].chapters_url.as_deref();
+        assert_eq!(chapters, Some("https://example.org/1.json"));
+    }
+
+    #[test]
+    fn parse_new_feed_url() {
+        const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Moved</title>
+    <itunes:new-feed-url>https://example.org/new</itunes:new-feed-url>
+  </channel>
+</rss>"#;
+        let feed = parse(RSS, "https://example.org/rss".to_owned()).unwrap();
+        assert_eq!(feed.new_url.as_deref(), Some("https://example.org/new"));
+        assert_eq!(feed.podcast.url, "https://example.org/rss");
+    }
+
+    #[test]
+    fn parse_json() {
+        let feed = parse(JSON, "https://example.org/feed.json".to_owned()).unwrap();
+        assert_eq!(feed.podcast.title, "JSON Example Podcast");
+        assert_eq!(feed.image.as_deref(), Some("https://example.org/icon.png"));
+        assert_eq!(feed.episodes.len(), 4);
+        let first = &feed.episodes[0];
+        assert_eq!(first.title, "Episode 2: Attachments");
+        assert_eq!(first.stream_url, "https://example.org/media/episode2.m4a");
+        assert_eq!(first.mime_type.as_deref(), Some("audio/x-m4a"));
+        assert_eq!(first.duration, 1800.);
+        // untitled items are named after their summary or text
+        assert_eq!(feed.episodes[2].title, "An episode without a title");
+        assert_eq!(feed.episodes[3].title, "Only text for this episode");
+    }
+
+    #[test]
+    fn itunes_duration() {
+        assert_eq!(parse_itunes_duration("1:02:03"), Some(3723.));
+        assert_eq!(parse_itunes_duration("31:02"), Some(1862.));
+        assert_eq!(parse_itunes_duration("95"), Some(95.));
+        assert_eq!(parse_itunes_duration("abc"), None);
+    }
+}
@@ -0,0 +1,79 @@
+use super::{parse_itunes_duration, Error, Feed};
+use crate::database::{Date, EpisodeExt, Podcast};
+use atom_syndication::{Entry, Link};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+pub fn parse(body: &str, url: String) -> Result<Feed, Error> {
+    let feed = atom_syndication::Feed::from_str(body)?;
+    let podcast = Podcast {
+        title: feed.title().as_str().to_owned(),
+        url,
+    };
+    let episodes = feed
+        .entries()
+        .iter()
+        .filter(|e| enclosure(e).is_some()) // skip entries that are not episodes
+        .map(|e| to_episode_ext(e, &podcast.title))
+        .collect::<Result<_, _>>()?;
+    let image = feed.logo().or_else(|| feed.icon()).map(str::to_owned);
+    Ok(Feed {
+        podcast,
+        episodes,
+        new_url: None,
+        image,
+    })
+}
+
+fn enclosure(entry: &Entry) -> Option<&Link> {
+    entry.links().iter().find(|l| l.rel() == "enclosure")
+}
+
+fn duration_from_extensions(entry: &Entry) -> Option<f32> {
+    let itunes = entry.extensions().get("itunes")?;
+    let duration = itunes.get("duration")?.first()?;
+    duration.value().and_then(parse_itunes_duration)
+}
+
+/// url of the Podcasting 2.0 chapters (`podcast:chapters`), only
+/// the json format is supported
+fn chapters_from_extensions(entry: &Entry) -> Option<String> {
+    let podcast = entry.extensions().get("podcast")?;
+    let chapters = podcast.get("chapters")?.first()?;
+    let json = chapters
+        .attrs()
+        .get("type")
+        .map(|t| t.contains("json"))
+        .unwrap_or(true);
+    if !json {
+        return None;
+    }
+    chapters.attrs().get("url").cloned()
+}
+
+fn to_episode_ext(entry: &Entry, podcast_title: &str) -> Result<EpisodeExt, Error> {
+    let enclosure = enclosure(entry).ok_or(Error::MissingStreamUrl)?;
+    let stream_url = enclosure.href().to_owned();
+    let mime_type = enclosure.mime_type().map(str::to_owned);
+    // atom has no standard way to specify the duration, zero marks it unknown
+    let duration = duration_from_extensions(entry).unwrap_or(0f32);
+    let title = entry.title().as_str();
+    if title.is_empty() {
+        return Err(Error::MissingEpisodeTitle);
+    }
+    let published = entry.published().unwrap_or_else(|| entry.updated());
+    let published: DateTime<Utc> = DateTime::from(*published);
+
+    Ok(EpisodeExt {
+        guid: Some(entry.id().to_owned()),
+        stream_url,
+        mime_type,
+        duration,
+        title: title.to_owned(),
+        podcast: podcast_title.to_owned(),
+        date: Date::from_published(Some(published)),
+        chapters_url: chapters_from_extensions(entry),
+        chapters: Vec::new(),
+        chapters_fetched: false,
+    })
+}
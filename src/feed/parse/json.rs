@@ -0,0 +1,99 @@
+use super::{Error, Feed};
+use crate::database::{Date, EpisodeExt, Podcast};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+/// subset of json feed 1.1 needed for podcasts, see: https://jsonfeed.org/version/1.1
+#[derive(Deserialize)]
+struct JsonFeed {
+    title: String,
+    /// the large image, favicon is too small for artwork
+    icon: Option<String>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+/// titles made from the text of an item are cut off at this many characters
+const TITLE_LEN: usize = 80;
+
+#[derive(Deserialize)]
+struct Item {
+    id: String,
+    title: Option<String>,
+    summary: Option<String>,
+    content_text: Option<String>,
+    date_published: Option<DateTime<Utc>>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    url: String,
+    mime_type: String,
+    duration_in_seconds: Option<f32>,
+}
+
+pub fn parse(body: &str, url: String) -> Result<Feed, Error> {
+    let feed: JsonFeed = serde_json::from_str(body)?;
+    let podcast = Podcast {
+        title: feed.title,
+        url,
+    };
+    let episodes = feed
+        .items
+        .into_iter()
+        .filter(|i| audio_attachment(i).is_some()) // skip items that are not episodes
+        .filter(|i| title(i).is_some())
+        .map(|i| to_episode_ext(i, &podcast.title))
+        .collect::<Result<_, _>>()?;
+    Ok(Feed {
+        podcast,
+        episodes,
+        new_url: None,
+        image: feed.icon,
+    })
+}
+
+fn audio_attachment(item: &Item) -> Option<&Attachment> {
+    item.attachments
+        .iter()
+        .find(|a| a.mime_type.starts_with("audio/"))
+}
+
+/// items need not have a title, those without use the summary or the
+/// first line of their text
+fn title(item: &Item) -> Option<String> {
+    let not_empty = |s: &&str| !s.trim().is_empty();
+    if let Some(title) = item.title.as_deref().filter(not_empty) {
+        return Some(title.to_owned());
+    }
+    if let Some(summary) = item.summary.as_deref().filter(not_empty) {
+        return Some(summary.to_owned());
+    }
+    let text = item.content_text.as_deref()?;
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty())?;
+    Some(line.chars().take(TITLE_LEN).collect())
+}
+
+fn to_episode_ext(item: Item, podcast_title: &str) -> Result<EpisodeExt, Error> {
+    let attachment = audio_attachment(&item).ok_or(Error::MissingStreamUrl)?;
+    let stream_url = attachment.url.clone();
+    let mime_type = Some(attachment.mime_type.clone());
+    // zero marks the duration as unknown
+    let duration = attachment.duration_in_seconds.unwrap_or(0f32);
+    let title = title(&item).ok_or(Error::MissingEpisodeTitle)?;
+
+    Ok(EpisodeExt {
+        guid: Some(item.id),
+        stream_url,
+        mime_type,
+        duration,
+        title,
+        podcast: podcast_title.to_owned(),
+        date: Date::from_published(item.date_published),
+        chapters_url: None,
+        chapters: Vec::new(),
+        chapters_fetched: false,
+    })
+}
@@ -0,0 +1,107 @@
+use super::{parse_itunes_duration, Error, Feed};
+use crate::database::{Date, EpisodeExt, Podcast};
+use std::str::FromStr;
+
+pub fn parse(body: &str, url: String) -> Result<Feed, Error> {
+    let channel = rss::Channel::from_str(body)?;
+    let podcast = Podcast {
+        title: channel.title().to_owned(),
+        url,
+    };
+    let episodes = channel
+        .items()
+        .iter()
+        .map(|i| to_episode_ext(i, &podcast.title))
+        .collect::<Result<_, _>>()?;
+    let new_url = channel
+        .itunes_ext()
+        .and_then(|ext| ext.new_feed_url())
+        .map(str::to_owned);
+    let image = channel
+        .itunes_ext()
+        .and_then(|ext| ext.image())
+        .or_else(|| channel.image().map(|i| i.url()))
+        .map(str::to_owned);
+    Ok(Feed {
+        podcast,
+        episodes,
+        new_url,
+        image,
+    })
+}
+
+fn url_from_extensions(item: &rss::Item) -> Option<String> {
+    let media = item.extensions().get("media")?;
+    let content = media.get("content")?;
+    let extention = content.first()?;
+    if extention.name() != "media:content" {
+        return None;
+    }
+    extention.attrs().get("url").cloned()
+}
+
+fn duration_from_extensions(item: &rss::Item) -> Option<f32> {
+    let media = item.extensions().get("media")?;
+    let content = media.get("content")?;
+    let extention = content.first()?;
+    if extention.name() != "media:content" {
+        return None;
+    }
+    extention
+        .attrs()
+        .get("duration")
+        .and_then(|u| u.parse().ok())
+}
+
+/// url of the Podcasting 2.0 chapters (`podcast:chapters`), only
+/// the json format is supported
+fn chapters_from_extensions(item: &rss::Item) -> Option<String> {
+    let podcast = item.extensions().get("podcast")?;
+    let chapters = podcast.get("chapters")?.first()?;
+    let json = chapters
+        .attrs()
+        .get("type")
+        .map(|t| t.contains("json"))
+        .unwrap_or(true);
+    if !json {
+        return None;
+    }
+    chapters.attrs().get("url").cloned()
+}
+
+fn to_episode_ext(item: &rss::Item, podcast_title: &str) -> Result<EpisodeExt, Error> {
+    //try to get the url from the description of the media object
+    let stream_url = item.enclosure().map(|encl| encl.url().to_owned());
+    let mime_type = item
+        .enclosure()
+        .map(|encl| encl.mime_type().to_owned())
+        .filter(|t| !t.is_empty());
+
+    //try to get the url and duration possible extensions
+    let stream_url = stream_url.or_else(|| url_from_extensions(item));
+    let duration = duration_from_extensions(item);
+
+    //try to get duration from any included itunes extensions
+    let duration = duration.or_else(|| {
+        item.itunes_ext()
+            .and_then(|ext| ext.duration().and_then(parse_itunes_duration))
+    });
+
+    let stream_url = stream_url.ok_or(Error::MissingStreamUrl)?;
+    let duration = duration.ok_or(Error::MissingDuration)?;
+    let title = item.title().ok_or(Error::MissingEpisodeTitle)?;
+    let podcast = podcast_title.to_owned();
+
+    Ok(EpisodeExt {
+        guid: item.guid().map(|g| g.value().to_owned()),
+        stream_url,
+        mime_type,
+        duration,
+        title: title.to_owned(),
+        podcast,
+        date: Date::from_item(item),
+        chapters_url: chapters_from_extensions(item),
+        chapters: Vec::new(),
+        chapters_fetched: false,
+    })
+}
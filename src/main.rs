@@ -1,10 +1,14 @@
 mod database;
 mod download;
 mod feed;
+mod mpris;
 mod page;
 mod play;
+mod retry;
 
-use database::{EpisodeKey, PodcastDb, PodcastKey, Progress};
+use database::{
+    DownloadQueue, EndAction, EpisodeKey, PlayQueue, PodcastDb, PodcastKey, Progress, Shortcut,
+};
 use download::Downloader;
 use download::FileType;
 use error_level::ErrorLevel;
@@ -17,85 +21,320 @@ use std::collections::HashMap;
 #[derive(Clone, Debug)]
 pub enum Message {
     ToEpisodes(PodcastKey),
-    ToEpisodesFinish(HashMap<u64, FileType>, PodcastKey),
+    ToEpisodesFinish(HashMap<EpisodeKey, FileType>, PodcastKey),
     PlayBackTick(std::time::Instant),
-    Stream(EpisodeKey),
+    Stream(EpisodeKey, f32),
     Play(EpisodeKey, FileType, f32),
     Download(EpisodeKey),
+    ToDownloads,
+    PauseDownload(EpisodeKey),
+    ResumeDownload(EpisodeKey),
+    RetryDownload(EpisodeKey),
+    CancelDownload(EpisodeKey),
+    DownloadCancelled,
+    ToQueue,
+    QueueNext(EpisodeKey),
+    QueueLast(EpisodeKey),
+    QueueMove(EpisodeKey, i32),
+    QueueRemove(EpisodeKey),
+    QueueClear,
+    PlayQueued(EpisodeKey),
     Remove(EpisodeKey, FileType),
     Back,
     Up,
     Down,
     PlayPause,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    KeyPressed(String),
+    ToShortcuts,
+    RebindShortcut(Shortcut),
     AddPodcast(String),
-    PodcastsUpdated,
+    PodcastsUpdated(Vec<(String, feed::Refreshed)>),
     StreamProgress(play::subscribe::Progress),
-    DownloadProgress(download::Progress),
-    DownloadFinished(HashMap<u64, FileType>),
+    DownloadProgress(EpisodeKey, download::Progress),
+    DownloadFinished(HashMap<EpisodeKey, FileType>),
+    OrphanedDownloads(Vec<EpisodeKey>),
     Skip(f32),
+    NextChapter,
+    PreviousChapter,
+    CycleSpeed,
+    CycleEndAction,
+    CycleSleepTimer,
+    ExtendSleepTimer,
+    Mpris(mpris::Command),
+    CyclePodcastSpeed(PodcastKey),
     SearchSubmit,
     SearchInputChanged(String),
     SearchResults(Vec<feed::SearchResult>),
     AddedPodcast(String, PodcastKey),
+    AddPodcastFailed(String),
+    ImportedOpml(Vec<feed::opml::ImportResult>),
+    ImportOpmlFailed(String),
+    ExportOpml,
+    ExportedOpml(Option<std::path::PathBuf>),
+    RemovePodcast(PodcastKey),
+    CancelRemovePodcast,
+    ConfirmRemovePodcast(PodcastKey, bool),
+    RemovedPodcastFiles,
+}
+
+impl Message {
+    pub fn added_podcast(res: Result<(String, PodcastKey), String>) -> Self {
+        match res {
+            Ok((title, id)) => Message::AddedPodcast(title, id),
+            Err(e) => Message::AddPodcastFailed(e),
+        }
+    }
+    pub fn imported_opml(res: Result<Vec<feed::opml::ImportResult>, feed::opml::Error>) -> Self {
+        match res {
+            Ok(report) => Message::ImportedOpml(report),
+            Err(e) => Message::ImportOpmlFailed(e.to_string()),
+        }
+    }
 }
 
 pub struct App {
     current: Page,
     podcasts: page::Podcasts,
     episodes: page::Episodes,
+    downloads: page::Downloads,
+    queue: page::Queue,
+    shortcuts: page::Shortcuts,
     downloader: Downloader,
     player: Player,
     controls: Controls, //Should only be needed on desktop platforms
     pod_db: PodcastDb,
+    settings: database::Settings,
+    mpris: mpris::Mpris,
 }
 
 fn update_podcasts(pod_db: PodcastDb) -> Command<Message> {
-    async fn update(pod_db: PodcastDb) {
-        pod_db.update_podcasts().await.unwrap();
+    async fn update(pod_db: PodcastDb) -> Vec<(String, feed::Refreshed)> {
+        pod_db.update_podcasts().await.unwrap()
     }
 
-    Command::perform(update(pod_db), |_| Message::PodcastsUpdated)
+    Command::perform(update(pod_db), Message::PodcastsUpdated)
 }
 
-impl Application for App {
-    type Executor = executor::Default;
-    type Message = Message;
-    type Flags = ();
+impl App {
+    /// play from file if it was downloaded, otherwise stream
+    fn play(&mut self, key: EpisodeKey, pos: f32) {
+        if let Err(e) = self.pod_db.get_episode_ext(key) {
+            log::error!("can not play episode: {}", e);
+            return;
+        }
+        match download::downloaded(key) {
+            Some(file_type) => self.player.add_file(key, file_type, pos),
+            None => self.player.add_stream(key, pos),
+        }
+    }
 
-    fn new(_flags: Self::Flags) -> (App, Command<Self::Message>) {
-        let db = database::open().unwrap();
-        let pod_db = PodcastDb::open(&db).unwrap();
-        let startup = update_podcasts(pod_db.clone());
-        (
-            App {
-                podcasts: page::Podcasts::from_db(pod_db.clone()),
-                episodes: page::Episodes::from_db(pod_db.clone()),
-                current: Page::Podcasts,
-                player: Player::from_db(pod_db.clone()),
-                downloader: Downloader::default(),
-                controls: Controls::default(),
-                pod_db,
-            },
-            startup,
-        )
+    /// play an episode from where it was left off
+    fn play_saved(&mut self, key: EpisodeKey) {
+        match self.pod_db.get_episode(key) {
+            Ok(episode) => self.play(key, episode.progress.into()),
+            Err(e) => log::error!("can not play episode: {}", e),
+        }
     }
-    fn title(&self) -> String {
-        String::from("Podcasts")
+
+    fn episode_ended(&mut self, key: EpisodeKey) {
+        self.pod_db
+            .update_episode_progress(key, Progress::Completed);
+        self.episodes.set_progress(key, Progress::Completed);
+
+        if self.player.sleep_at_end() {
+            return self.player.clear();
+        }
+        // the queue takes precedence over the end of episode action
+        if let Some(next) = self.queue.pop_front() {
+            return self.play_saved(next);
+        }
+        let next = match self.settings.end_action() {
+            EndAction::Stop => None,
+            EndAction::PlayNext => self.pod_db.next_unplayed(key).unwrap_or_else(|e| {
+                log::error!("could not find next episode: {}", e);
+                None
+            }),
+        };
+        match next {
+            Some((next, episode)) => self.play(next, episode.progress.into()),
+            None => self.player.clear(),
+        }
     }
-    fn update(&mut self, message: Self::Message, _clip: &mut iced::Clipboard) -> Command<Self::Message> {
+
+    /// use the speed for what is playing and remember it
+    fn change_speed(&mut self, speed: f32) {
+        self.player.set_speed(speed);
+        let playing = self.player.current.info().map(|i| i.id.podcast());
+        match playing {
+            // the podcast has its own speed, change that
+            Some(id) if self.settings.podcast_speed(id).is_some() => {
+                if let Err(e) = self.settings.set_podcast_speed(id, Some(speed)) {
+                    log::error!("could not store podcast speed: {}", e);
+                }
+            }
+            _ => {
+                if let Err(e) = self.settings.set_global_speed(speed) {
+                    log::error!("could not store playback speed: {}", e);
+                }
+            }
+        }
+    }
+
+    fn store_position(&mut self) {
+        if let Some(info) = self.player.current.info() {
+            let progress = Progress::Listening(self.player.pos());
+            self.pod_db.update_episode_progress(info.id, progress);
+            self.episodes.set_progress(info.id, progress);
+        }
+    }
+
+    fn pause(&mut self) -> Command<Message> {
+        if self.player.is_playing() {
+            return self.player.play_pause();
+        }
+        Command::none()
+    }
+
+    fn stop(&mut self) {
+        self.store_position();
+        self.player.clear();
+    }
+
+    /// the first queued episode or else the next unplayed episode of
+    /// the podcast
+    fn next(&mut self) {
+        let current = match self.player.current.info() {
+            Some(info) => info.id,
+            None => return,
+        };
+        if let Some(next) = self.queue.pop_front() {
+            self.store_position();
+            return self.play_saved(next);
+        }
+        match self.pod_db.next_unplayed(current) {
+            Ok(Some((next, episode))) => {
+                self.store_position();
+                self.play(next, episode.progress.into());
+            }
+            Ok(None) => log::info!("no next episode to play"),
+            Err(e) => log::error!("could not find next episode: {}", e),
+        }
+    }
+
+    /// restart the episode, or go back to the one played before if
+    /// it just started. The current episode is queued to play next.
+    fn previous(&mut self) {
+        const RESTART_AFTER: f32 = 5.;
+        let current = match self.player.current.info() {
+            Some(info) => info.id,
+            None => return,
+        };
+        let pos = self.player.pos();
+        if pos > RESTART_AFTER {
+            return self.player.skip(-pos);
+        }
+        match self.player.previous_episode() {
+            Some(previous) => {
+                self.stop();
+                self.queue.play_next(current);
+                self.play_saved(previous);
+            }
+            None => self.player.skip(-pos),
+        }
+    }
+
+    fn key_pressed(&mut self, key: String) -> Command<Message> {
+        if self.current == Page::Shortcuts && self.shortcuts.key_pressed(&key) {
+            return Command::none();
+        }
+        let shortcut = match self.settings.shortcut_for(&key) {
+            Some(shortcut) => shortcut,
+            None => return Command::none(),
+        };
+        match shortcut {
+            Shortcut::SkipForward => self.player.skip(self.player.skip_dur()),
+            Shortcut::SkipBackward => self.player.skip(-self.player.skip_dur()),
+            Shortcut::SpeedUp => self.change_speed(play::faster(self.player.speed())),
+            Shortcut::SpeedDown => self.change_speed(play::slower(self.player.speed())),
+            Shortcut::NextChapter => self.player.next_chapter(),
+            Shortcut::PreviousChapter => self.player.previous_chapter(),
+        }
+        Command::none()
+    }
+
+    fn mpris_metadata(&self, key: EpisodeKey) -> Option<mpris::Metadata> {
+        let episode = self.pod_db.get_episode_ext(key).ok()?;
+        let art_url = self.pod_db.get_artwork(key.podcast()).unwrap_or_else(|e| {
+            log::error!("could not read podcast artwork: {}", e);
+            None
+        });
+        Some(mpris::Metadata {
+            id: key.to_string(),
+            title: episode.title,
+            podcast: episode.podcast,
+            art_url,
+            length: episode.known_duration(),
+        })
+    }
+
+    fn update_mpris(&self) {
+        let mut state = self.mpris.state();
+        let playing = self.player.current.info().map(|i| i.id);
+        let shown = state.metadata.as_ref().map(|m| m.id.clone());
+        if playing.map(|k| k.to_string()) != shown {
+            state.metadata = playing.and_then(|k| self.mpris_metadata(k));
+        }
+        state.status = match playing {
+            None => mpris::Status::Stopped,
+            Some(_) if self.player.is_playing() => mpris::Status::Playing,
+            Some(_) => mpris::Status::Paused,
+        };
+        state.position = self.player.pos();
+        state.rate = self.player.speed();
+        state.seeks = self.player.seeks();
+        self.mpris.update(state);
+    }
+
+    fn mpris_command(&mut self, command: mpris::Command) -> Command<Message> {
+        use mpris::Command as C;
+        match command {
+            C::PlayPause => return self.player.play_pause(),
+            C::Play if !self.player.is_playing() => return self.player.play_pause(),
+            C::Play => (),
+            C::Pause => return self.pause(),
+            C::Stop => self.stop(),
+            C::Next => self.next(),
+            C::Previous => self.previous(),
+            C::Seek(offset) => self.player.skip(offset),
+            C::SetPosition(pos) => self.player.skip(pos - self.player.pos()),
+            C::SetRate(speed) => self.change_speed(speed),
+        }
+        Command::none()
+    }
+
+    fn handle(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::Back => self.current.back(),
             Message::Up => match &self.current {
                 Page::Podcasts => self.podcasts.up(),
                 Page::Episodes => self.episodes.up(),
+                Page::Downloads => self.downloads.up(),
+                Page::Queue => self.queue.up(),
+                Page::Shortcuts => (),
             },
             Message::Down => match &self.current {
                 Page::Podcasts => self.podcasts.down(),
                 Page::Episodes => self.episodes.down(),
+                Page::Downloads => self.downloads.down(),
+                Page::Queue => self.queue.down(),
+                Page::Shortcuts => (),
             },
             Message::ToEpisodes(podcast_id) => {
-                let podcast = self.pod_db.get_podcast(podcast_id).unwrap();
-                return Command::perform(download::scan_podcast_dir(podcast.title), move |set| {
+                return Command::perform(download::scan_podcast_dir(podcast_id), move |set| {
                     Message::ToEpisodesFinish(set, podcast_id)
                 });
             }
@@ -105,56 +344,182 @@ impl Application for App {
             }
             Message::StreamProgress(p) => {
                 use play::subscribe::Progress;
+                let mut kept_as_download = None;
                 match p {
-                    Progress::ToShortError => log::warn!("stream was to short to play audio"),
                     Progress::StreamError(e) => log::error!("errored stream {}", e),
-                    Progress::Started(rx) => self.player.rx = Some(rx),
-                    Progress::Finished => (),
-                    Progress::Advanced(p) => {
-                        self.player.current.set_streampos(p);
-                        if self.player.stream_ready(p) {
-                            self.player.start_stream();
+                    Progress::Started(stream, file_type) => {
+                        self.player.stream = Some(stream);
+                        if file_type.is_some() {
+                            self.player.stream_type = file_type;
                         }
                     }
+                    Progress::Advanced(p) => self.player.current.set_streampos(p),
+                    Progress::Finished => {
+                        self.player.current.set_streampos(100.);
+                        kept_as_download = self.player.current.info().map(|i| i.id.podcast());
+                    }
+                }
+                self.player.buffer_tick();
+                if let Some(podcast_id) = kept_as_download {
+                    if self.episodes.podcast_id == Some(podcast_id) {
+                        return Command::perform(
+                            download::scan_podcast_dir(podcast_id),
+                            Message::DownloadFinished,
+                        );
+                    }
                 }
             }
-            Message::DownloadProgress(download::Progress::Error(e)) => e.log_error(),
-            Message::DownloadProgress(download::Progress::Finished) => {
+            Message::DownloadProgress(key, download::Progress::Error(e)) => {
+                e.log_error();
+                self.downloads.update(key, &download::Progress::Error(e));
+                self.downloader.errored(key);
+            }
+            Message::DownloadProgress(key, download::Progress::Finished) => {
                 log::info!("finished download");
-                let podcast = self.episodes.podcast.as_ref().unwrap().clone();
-                return Command::perform(
-                    download::scan_podcast_dir(podcast),
-                    Message::DownloadFinished,
-                );
+                self.downloader.finished(key);
+                self.downloads.update(key, &download::Progress::Finished);
+                let podcast_id = key.podcast();
+                if self.episodes.podcast_id == Some(podcast_id) {
+                    return Command::perform(
+                        download::scan_podcast_dir(podcast_id),
+                        Message::DownloadFinished,
+                    );
+                }
             }
-            Message::DownloadProgress(_) => (),
+            Message::DownloadProgress(key, progress) => self.downloads.update(key, &progress),
             Message::DownloadFinished(set) => self.episodes.update_downloaded(set),
+            Message::OrphanedDownloads(keys) => {
+                for key in self.downloader.resume_orphaned(keys, &self.pod_db) {
+                    self.downloads.add(key);
+                }
+            }
             Message::PlayBackTick(_) => {
-                if let Some(pos) = self.player.should_store_pos() {
+                self.player.sleep_tick();
+                self.player.buffer_tick();
+                if let Some(key) = self.player.track_ended() {
+                    self.episode_ended(key);
+                } else if let Some(pos) = self.player.should_store_pos() {
                     if let Some(info) = self.player.current.info() {
                         let progress = Progress::Listening(pos);
                         self.pod_db.update_episode_progress(info.id, progress);
+                        self.episodes.set_progress(info.id, progress);
                     }
                 }
                 // also used to trigger a redraw
             }
             Message::AddPodcast(url) => {
                 let pod_db = self.pod_db.clone();
-                return Command::perform(feed::add_podcast(pod_db, url), |(title, id)| {
-                    Message::AddedPodcast(title, id)
-                });
+                return Command::perform(feed::add_podcast(pod_db, url), Message::added_podcast);
             }
-            Message::PodcastsUpdated => {
+            Message::PodcastsUpdated(results) => {
+                use feed::Refreshed;
+                let mut any_updated = false;
+                for (title, refreshed) in results {
+                    match refreshed {
+                        Refreshed::Unchanged => log::debug!("feed unchanged: {}", title),
+                        Refreshed::Updated => {
+                            log::info!("feed updated: {}", title);
+                            any_updated = true;
+                        }
+                        Refreshed::Failed(e) => log::warn!("could not refresh {}: {}", title, e),
+                    }
+                }
+                if !any_updated {
+                    return Command::none();
+                }
                 if let Page::Episodes = self.current {
                     self.episodes.repopulate(HashMap::new());
                 }
             }
-            Message::Stream(key) => self.player.add_stream(key),
-            Message::Play(key, file_type, pos) => self.player.add_file(key, file_type, pos),
+            Message::Stream(key, pos) => {
+                self.queue.remove(key);
+                self.player.add_stream(key, pos);
+            }
+            Message::Play(key, file_type, pos) => {
+                self.queue.remove(key);
+                self.player.add_file(key, file_type, pos);
+            }
             Message::Skip(f) => self.player.skip(f),
-            Message::Download(key) => return self.downloader.add(key, &mut self.pod_db),
-            Message::Remove(_key, _file_type) => todo!(),
+            Message::NextChapter => self.player.next_chapter(),
+            Message::PreviousChapter => self.player.previous_chapter(),
+            Message::CycleSpeed => self.change_speed(play::next_speed(self.player.speed())),
+            Message::CycleEndAction => {
+                let action = self.settings.end_action().next();
+                if let Err(e) = self.settings.set_end_action(action) {
+                    log::error!("could not store end of episode action: {}", e);
+                }
+            }
+            Message::CycleSleepTimer => self.player.cycle_sleep_timer(),
+            Message::ExtendSleepTimer => self.player.extend_sleep_timer(),
+            Message::Mpris(command) => return self.mpris_command(command),
+            Message::CyclePodcastSpeed(id) => {
+                // cycles from the global default through the speeds
+                let speed = match self.settings.podcast_speed(id) {
+                    None => Some(play::next_speed(0.)),
+                    Some(s) if play::next_speed(s) < s => None,
+                    Some(s) => Some(play::next_speed(s)),
+                };
+                if let Err(e) = self.settings.set_podcast_speed(id, speed) {
+                    log::error!("could not store podcast speed: {}", e);
+                }
+                let playing = self.player.current.info().map(|i| i.id.podcast());
+                if playing == Some(id) {
+                    self.player.set_speed(self.settings.speed(id));
+                }
+            }
+            Message::Download(key) => {
+                self.downloads.add(key);
+                return self.downloader.add(key, &self.pod_db);
+            }
+            Message::ToDownloads => self.current = Page::Downloads,
+            Message::PauseDownload(key) => {
+                self.downloader.pause(key);
+                self.downloads.paused(key);
+            }
+            Message::ResumeDownload(key) => {
+                self.downloader.resume(key);
+                self.downloads.queued(key);
+            }
+            Message::RetryDownload(key) => {
+                self.downloader.retry(key);
+                self.downloads.queued(key);
+            }
+            Message::CancelDownload(key) => {
+                self.downloads.remove(key);
+                return self.downloader.cancel(key);
+            }
+            Message::DownloadCancelled => (),
+            Message::ToQueue => self.current = Page::Queue,
+            Message::QueueNext(key) => self.queue.play_next(key),
+            Message::QueueLast(key) => self.queue.play_last(key),
+            Message::QueueMove(key, by) => self.queue.shift(key, by),
+            Message::QueueRemove(key) => self.queue.remove(key),
+            Message::QueueClear => self.queue.clear(),
+            Message::PlayQueued(key) => {
+                self.queue.remove(key);
+                self.play_saved(key);
+            }
+            Message::Remove(key, file_type) => {
+                // the stream cache is removed too, so a stream stops as well
+                if self.player.current.info().map(|i| i.id) == Some(key) {
+                    self.player.clear();
+                }
+                let podcast_id = key.podcast();
+                let path = download::base_file_path(key);
+                let remove = async move {
+                    download::remove_file(path, file_type).await;
+                    download::scan_podcast_dir(podcast_id).await
+                };
+                return Command::perform(remove, Message::DownloadFinished);
+            }
             Message::PlayPause => return self.player.play_pause(),
+            Message::Pause => return self.pause(),
+            Message::Stop => self.stop(),
+            Message::Next => self.next(),
+            Message::Previous => self.previous(),
+            Message::KeyPressed(key) => return self.key_pressed(key),
+            Message::ToShortcuts => self.current = Page::Shortcuts,
+            Message::RebindShortcut(shortcut) => self.shortcuts.rebind(shortcut),
             Message::SearchSubmit => return self.podcasts.search.do_search(true),
             Message::SearchInputChanged(input) => {
                 return self
@@ -168,17 +533,120 @@ impl Application for App {
                 self.podcasts.search.reset();
                 self.podcasts.list.add(title, id);
             }
+            Message::AddPodcastFailed(e) => {
+                self.podcasts.list.remove_feedres();
+                self.podcasts.list.show_error(e);
+            }
+            Message::ImportedOpml(report) => {
+                self.podcasts.list.remove_feedres();
+                self.podcasts.search.reset();
+                self.podcasts.list.add_imported(report);
+            }
+            Message::ImportOpmlFailed(e) => {
+                self.podcasts.list.remove_feedres();
+                self.podcasts.list.show_error(e);
+            }
+            Message::ExportOpml => {
+                let pod_db = self.pod_db.clone();
+                return Command::perform(feed::opml::export_to_file(pod_db), |res| {
+                    Message::ExportedOpml(res.map_err(|e| log::error!("{}", e)).ok())
+                });
+            }
+            Message::ExportedOpml(path) => {
+                if let Some(path) = path {
+                    log::info!("exported subscriptions to: {:?}", path);
+                }
+            }
+            Message::RemovePodcast(id) => self.podcasts.list.confirm_remove(id),
+            Message::CancelRemovePodcast => self.podcasts.list.cancel_remove(),
+            Message::ConfirmRemovePodcast(id, remove_files) => {
+                let playing = self.player.current.info().map(|i| i.id.podcast());
+                if playing == Some(id) {
+                    self.player.clear();
+                }
+                self.podcasts.list.remove(id);
+                if let Err(e) = self.pod_db.remove_podcast(id) {
+                    log::error!("could not remove podcast from database: {}", e);
+                }
+                // drops the removed episodes from the queue
+                self.queue.repopulate();
+                if let Err(e) = self.settings.remove_podcast(id) {
+                    log::error!("could not remove podcast settings: {}", e);
+                }
+                // otherwise they resume on the next start
+                let mut commands = Vec::new();
+                for key in self.downloader.of_podcast(id) {
+                    self.downloads.remove(key);
+                    commands.push(self.downloader.cancel(key));
+                }
+                if remove_files {
+                    commands.push(Command::perform(download::remove_podcast_dir(id), |_| {
+                        Message::RemovedPodcastFiles
+                    }));
+                }
+                return Command::batch(commands);
+            }
+            Message::RemovedPodcastFiles => (),
         }
         Command::none()
     }
+}
+
+impl Application for App {
+    type Executor = executor::Default;
+    type Message = Message;
+    type Flags = ();
+
+    fn new(_flags: Self::Flags) -> (App, Command<Self::Message>) {
+        let db = database::open().unwrap();
+        let pod_db = PodcastDb::open(&db).unwrap();
+        let download_queue = DownloadQueue::open(&db).unwrap();
+        let settings = database::Settings::open(&db).unwrap();
+        let play_queue = PlayQueue::open(&db).unwrap();
+        let downloader = Downloader::from_db(download_queue, &pod_db);
+        let startup = Command::batch(vec![
+            update_podcasts(pod_db.clone()),
+            Command::perform(
+                download::orphaned_parts(pod_db.clone()),
+                Message::OrphanedDownloads,
+            ),
+        ]);
+        (
+            App {
+                downloads: page::Downloads::from_db(pod_db.clone(), downloader.queued()),
+                podcasts: page::Podcasts::from_db(pod_db.clone()),
+                queue: page::Queue::from_db(pod_db.clone(), play_queue),
+                shortcuts: page::Shortcuts::from_settings(settings.clone()),
+                episodes: page::Episodes::from_db(pod_db.clone(), settings.clone()),
+                current: Page::Podcasts,
+                player: Player::from_db(pod_db.clone(), settings.clone()),
+                downloader,
+                controls: Controls::default(),
+                pod_db,
+                settings,
+                mpris: mpris::Mpris::new(),
+            },
+            startup,
+        )
+    }
+    fn title(&self) -> String {
+        String::from("Podcasts")
+    }
+    fn update(&mut self, message: Self::Message, _clip: &mut iced::Clipboard) -> Command<Self::Message> {
+        let command = self.handle(message);
+        // any message can change what is playing
+        self.update_mpris();
+        command
+    }
     fn subscription(&self) -> Subscription<Self::Message> {
         use play::Track;
         use std::time::Duration;
 
         let mut subs = Vec::new();
         match &self.player.current {
-            Track::Stream(_, _, url) => {
-                let stream = play::subscribe::play(url.to_owned()).map(Message::StreamProgress);
+            Track::Stream(_, _, url, path) => {
+                let stream = play::subscribe::play(url.to_owned(), path.to_owned())
+                    .map(Message::StreamProgress);
                 let time =
                     iced::time::every(Duration::from_millis(1000 / 6)).map(Message::PlayBackTick);
                 subs.push(stream);
@@ -191,7 +659,8 @@ impl Application for App {
             }
             _ => (),
         }
-        subs.push(play::handle_media_keys());
+        subs.push(play::handle_keys());
+        subs.push(self.mpris.subscription().map(Message::Mpris));
         subs.extend(self.downloader.subs());
         Subscription::batch(subs)
     }
@@ -199,6 +668,9 @@ impl Application for App {
         let content = match self.current {
             Page::Podcasts => self.podcasts.view(),
             Page::Episodes => self.episodes.view(),
+            Page::Downloads => self.downloads.view(),
+            Page::Queue => self.queue.view(),
+            Page::Shortcuts => self.shortcuts.view(),
         };
         let column = Column::new()
             .push(content)
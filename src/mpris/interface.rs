@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use tokio::sync::{mpsc, watch};
+use zbus::zvariant::{ObjectPath, Value};
+use zbus::{dbus_interface, Connection, ConnectionBuilder, SignalContext};
+
+pub const PATH: &str = "/org/mpris/MediaPlayer2";
+/// episodes are exposed as tracks under this path
+const TRACK_PATH: &str = "/org/pods/episode";
+/// the spec has no speed limits, these are those of the player
+const MINIMUM_RATE: f64 = 0.5;
+const MAXIMUM_RATE: f64 = 3.0;
+
+/// requests from the desktop, times are in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// skip forward or backward
+    Seek(f32),
+    SetPosition(f32),
+    SetRate(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::Playing => "Playing",
+            Status::Paused => "Paused",
+            Status::Stopped => "Stopped",
+        }
+    }
+}
+
+/// the episode that is playing
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    /// unique for the episode, only letters, digits and underscores
+    pub id: String,
+    pub title: String,
+    pub podcast: String,
+    pub art_url: Option<String>,
+    /// in seconds, if known
+    pub length: Option<f32>,
+}
+
+impl Metadata {
+    fn track_id(&self) -> String {
+        format!("{}/{}", TRACK_PATH, self.id)
+    }
+}
+
+/// what the desktop is shown, set by the app
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub status: Status,
+    pub metadata: Option<Metadata>,
+    /// in seconds
+    pub position: f32,
+    pub rate: f32,
+    /// increased every time the user skips through the episode
+    pub seeks: u32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            status: Status::Stopped,
+            metadata: None,
+            position: 0.,
+            rate: 1.,
+            seeks: 0,
+        }
+    }
+}
+
+fn micros(seconds: f32) -> i64 {
+    (seconds as f64 * 1e6) as i64
+}
+
+fn seconds(micros: i64) -> f32 {
+    (micros as f64 / 1e6) as f32
+}
+
+pub struct RootInterface;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl RootInterface {
+    fn raise(&self) {}
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "Pods"
+    }
+    #[dbus_interface(property)]
+    fn desktop_entry(&self) -> &str {
+        "pods"
+    }
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+pub struct PlayerInterface {
+    state: watch::Receiver<State>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl PlayerInterface {
+    fn send(&self, command: Command) {
+        // the app is shutting down if this fails
+        let _ = self.commands.send(command);
+    }
+    fn has_track(&self) -> bool {
+        self.state.borrow().metadata.is_some()
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn next(&self) {
+        self.send(Command::Next)
+    }
+    fn previous(&self) {
+        self.send(Command::Previous)
+    }
+    fn pause(&self) {
+        self.send(Command::Pause)
+    }
+    fn play_pause(&self) {
+        self.send(Command::PlayPause)
+    }
+    fn stop(&self) {
+        self.send(Command::Stop)
+    }
+    fn play(&self) {
+        self.send(Command::Play)
+    }
+    /// offset in microseconds
+    fn seek(&self, offset: i64) {
+        self.send(Command::Seek(seconds(offset)))
+    }
+    /// ignored if the track is no longer playing as the spec requires
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let current = match &self.state.borrow().metadata {
+            Some(metadata) => metadata.track_id(),
+            None => return,
+        };
+        if track_id.as_str() == current && position >= 0 {
+            self.send(Command::SetPosition(seconds(position)))
+        }
+    }
+    fn open_uri(&self, _uri: &str) {}
+
+    /// position in microseconds after it changed other then by playing
+    #[dbus_interface(signal)]
+    pub async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        self.state.borrow().status.as_str()
+    }
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        self.state.borrow().rate as f64
+    }
+    #[dbus_interface(property)]
+    fn set_rate(&self, rate: f64) {
+        // a rate of zero means pause according to the spec
+        if rate <= 0. {
+            self.send(Command::Pause)
+        } else {
+            let rate = rate.clamp(MINIMUM_RATE, MAXIMUM_RATE);
+            self.send(Command::SetRate(rate as f32))
+        }
+    }
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<&'static str, Value<'static>> {
+        let mut map = HashMap::new();
+        let state = self.state.borrow();
+        let metadata = match &state.metadata {
+            Some(metadata) => metadata,
+            None => return map,
+        };
+        let track_id = ObjectPath::try_from(metadata.track_id())
+            .unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(TRACK_PATH));
+        map.insert("mpris:trackid", Value::from(track_id));
+        if let Some(length) = metadata.length {
+            map.insert("mpris:length", Value::from(micros(length)));
+        }
+        map.insert("xesam:title", Value::from(metadata.title.clone()));
+        map.insert("xesam:album", Value::from(metadata.podcast.clone()));
+        map.insert("xesam:artist", Value::from(vec![metadata.podcast.clone()]));
+        if let Some(url) = &metadata.art_url {
+            map.insert("mpris:artUrl", Value::from(url.clone()));
+        }
+        map
+    }
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+    /// no change signal is send for this, clients poll it
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        micros(self.state.borrow().position)
+    }
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        MINIMUM_RATE
+    }
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        MAXIMUM_RATE
+    }
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        self.has_track()
+    }
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        self.has_track()
+    }
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        self.has_track()
+    }
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        self.has_track()
+    }
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        self.has_track()
+    }
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// serve both interfaces on the connection that is being build
+pub async fn serve(
+    builder: ConnectionBuilder<'_>,
+    state: watch::Receiver<State>,
+    commands: mpsc::UnboundedSender<Command>,
+) -> zbus::Result<Connection> {
+    let player = PlayerInterface { state, commands };
+    builder
+        .serve_at(PATH, RootInterface)?
+        .serve_at(PATH, player)?
+        .build()
+        .await
+}
+
+/// tell clients about changes to the state, returns once the app
+/// stops sending them
+pub async fn notify_changes(
+    connection: Connection,
+    mut state: watch::Receiver<State>,
+) -> zbus::Result<()> {
+    let iface = connection
+        .object_server()
+        .interface::<_, PlayerInterface>(PATH)
+        .await?;
+    let ctxt = iface.signal_context();
+    let mut last = state.borrow().clone();
+    while state.changed().await.is_ok() {
+        let new = state.borrow().clone();
+        let player = iface.get().await;
+        if new.status != last.status {
+            player.playback_status_changed(ctxt).await?;
+        }
+        if new.metadata != last.metadata {
+            player.metadata_changed(ctxt).await?;
+            player.can_play_changed(ctxt).await?;
+            player.can_pause_changed(ctxt).await?;
+            player.can_seek_changed(ctxt).await?;
+            player.can_go_next_changed(ctxt).await?;
+            player.can_go_previous_changed(ctxt).await?;
+        }
+        if new.rate != last.rate {
+            player.rate_changed(ctxt).await?;
+        }
+        if new.seeks != last.seeks {
+            PlayerInterface::seeked(ctxt, micros(new.position)).await?;
+        }
+        last = new;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use iced_futures::futures;
+    use std::convert::TryInto;
+    use zbus::zvariant::OwnedValue;
+
+    fn playing() -> State {
+        State {
+            status: Status::Playing,
+            metadata: Some(Metadata {
+                id: "0011223344556677".to_owned(),
+                title: "Episode".to_owned(),
+                podcast: "Podcast".to_owned(),
+                art_url: None,
+                length: Some(60.),
+            }),
+            position: 12.,
+            ..State::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn control_over_private_bus() {
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let (_state_tx, state_rx) = watch::channel(playing());
+        let (tx, mut commands) = mpsc::unbounded_channel();
+        let guid = zbus::Guid::generate();
+        let server = ConnectionBuilder::unix_stream(server).server(&guid).p2p();
+        let client = ConnectionBuilder::unix_stream(client).p2p().build();
+        let (_server, client) = futures::future::try_join(serve(server, state_rx, tx), client)
+            .await
+            .unwrap();
+
+        let proxy = zbus::Proxy::new(
+            &client,
+            "org.mpris.MediaPlayer2.pods",
+            PATH,
+            "org.mpris.MediaPlayer2.Player",
+        )
+        .await
+        .unwrap();
+        let status: String = proxy.get_property("PlaybackStatus").await.unwrap();
+        assert_eq!(status, "Playing");
+        let position: i64 = proxy.get_property("Position").await.unwrap();
+        assert_eq!(position, 12_000_000);
+        let metadata: HashMap<String, OwnedValue> = proxy.get_property("Metadata").await.unwrap();
+        let title: String = metadata["xesam:title"].clone().try_into().unwrap();
+        assert_eq!(title, "Episode");
+
+        proxy.call_method("PlayPause", &()).await.unwrap();
+        assert_eq!(commands.recv().await, Some(Command::PlayPause));
+        proxy.call_method("Seek", &(-5_000_000i64)).await.unwrap();
+        assert_eq!(commands.recv().await, Some(Command::Seek(-5.)));
+        let track = ObjectPath::try_from("/org/pods/episode/0011223344556677").unwrap();
+        proxy
+            .call_method("SetPosition", &(track, 30_000_000i64))
+            .await
+            .unwrap();
+        assert_eq!(commands.recv().await, Some(Command::SetPosition(30.)));
+    }
+}
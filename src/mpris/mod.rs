@@ -0,0 +1,109 @@
+//! Media controls over D-Bus (MPRIS). Lock screens, notifications and
+//! desktop shells use this to show and control what is playing, also
+//! while the window does not have focus.
+
+use iced_futures::futures;
+use tokio::sync::{mpsc, watch};
+use zbus::{Connection, ConnectionBuilder};
+
+mod interface;
+pub use interface::{Command, Metadata, State, Status};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.pods";
+
+/// app side of the service, the state set here is shown to clients
+pub struct Mpris {
+    state: watch::Sender<State>,
+    // kept so the subscription can start listening at any time
+    receiver: watch::Receiver<State>,
+}
+
+impl Mpris {
+    pub fn new() -> Self {
+        let (state, receiver) = watch::channel(State::default());
+        Self { state, receiver }
+    }
+    pub fn update(&self, state: State) {
+        if *self.receiver.borrow() != state {
+            // can not fail, we hold a receiver
+            let _ = self.state.send(state);
+        }
+    }
+    pub fn state(&self) -> State {
+        self.receiver.borrow().clone()
+    }
+    /// commands send by clients
+    pub fn subscription(&self) -> iced::Subscription<Command> {
+        iced::Subscription::from_recipe(Service {
+            state: self.receiver.clone(),
+        })
+    }
+}
+
+impl Default for Mpris {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Service {
+    state: watch::Receiver<State>,
+}
+
+enum Connecting {
+    Start(watch::Receiver<State>),
+    // the connection is dropped and the service stops with the stream
+    Running(Connection, mpsc::UnboundedReceiver<Command>),
+}
+
+async fn connect(
+    state: watch::Receiver<State>,
+) -> zbus::Result<(Connection, mpsc::UnboundedReceiver<Command>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let builder = ConnectionBuilder::session()?.name(BUS_NAME)?;
+    let connection = interface::serve(builder, state.clone(), tx).await?;
+    let notify = interface::notify_changes(connection.clone(), state);
+    tokio::spawn(async move {
+        if let Err(e) = notify.await {
+            log::error!("could not notify media control clients: {}", e);
+        }
+    });
+    Ok((connection, rx))
+}
+
+async fn next_command(state: Connecting) -> Option<(Command, Connecting)> {
+    let (connection, mut commands) = match state {
+        Connecting::Start(state) => match connect(state).await {
+            Ok(service) => service,
+            Err(e) => {
+                log::warn!("media controls (MPRIS) not available: {}", e);
+                return None;
+            }
+        },
+        Connecting::Running(connection, commands) => (connection, commands),
+    };
+    let command = commands.recv().await?;
+    Some((command, Connecting::Running(connection, commands)))
+}
+
+impl<H, I> iced_futures::subscription::Recipe<H, I> for Service
+where
+    H: std::hash::Hasher,
+{
+    type Output = Command;
+
+    fn hash(&self, state: &mut H) {
+        use std::hash::Hash;
+        std::any::TypeId::of::<Self>().hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: futures::stream::BoxStream<'static, I>,
+    ) -> futures::stream::BoxStream<'static, Self::Output> {
+        Box::pin(futures::stream::unfold(
+            Connecting::Start(self.state),
+            next_command,
+        ))
+    }
+}
@@ -0,0 +1,211 @@
+use iced::widget::scrollable::{self, Scrollable};
+use iced::Length;
+use iced::{button, Element, HorizontalAlignment, Row, Text};
+use std::time::Instant;
+
+use super::small_button;
+use crate::database::{EpisodeKey, PodcastDb};
+use crate::download;
+use crate::Message;
+
+#[derive(Debug, Clone)]
+enum Status {
+    Queued,
+    Downloading(f32),
+    Paused,
+    Retrying(u32),
+    Failed(String),
+    Finished,
+}
+
+/// download speed averaged over the last few updates
+#[derive(Debug, Default)]
+struct Speed {
+    last: Option<(Instant, u64)>,
+    bytes_per_sec: f32,
+}
+
+impl Speed {
+    fn update(&mut self, downloaded: u64) {
+        let now = Instant::now();
+        if let Some((then, before)) = self.last {
+            let elapsed = now.duration_since(then).as_secs_f32();
+            if elapsed > 0. {
+                let current = downloaded.saturating_sub(before) as f32 / elapsed;
+                self.bytes_per_sec = 0.8 * self.bytes_per_sec + 0.2 * current;
+            }
+        }
+        self.last = Some((now, downloaded));
+    }
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+    fn format(&self) -> String {
+        let kb = self.bytes_per_sec / 1000.;
+        if kb > 1000. {
+            format!("{:.1} MB/s", kb / 1000.)
+        } else {
+            format!("{:.0} kB/s", kb)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ListItem {
+    key: EpisodeKey,
+    title: String,
+    status: Status,
+    speed: Speed,
+    // pause, resume or retry
+    action_button: button::State,
+    cancel_button: button::State,
+}
+
+impl ListItem {
+    fn new(key: EpisodeKey, title: String, status: Status) -> Self {
+        Self {
+            key,
+            title,
+            status,
+            speed: Speed::default(),
+            action_button: button::State::new(),
+            cancel_button: button::State::new(),
+        }
+    }
+    fn describe(&self) -> String {
+        match &self.status {
+            Status::Queued => "queued".to_owned(),
+            Status::Downloading(p) => format!("{:.0}% {}", p, self.speed.format()),
+            Status::Paused => "paused".to_owned(),
+            Status::Retrying(n) => format!("retrying ({})", n),
+            Status::Failed(e) => format!("failed: {}", e),
+            Status::Finished => "done".to_owned(),
+        }
+    }
+    fn view(&mut self) -> Row<Message> {
+        let key = self.key;
+        let info = format!("{}\n{}", self.title, self.describe());
+        let mut row = Row::new().push(
+            Text::new(info)
+                .horizontal_alignment(HorizontalAlignment::Left)
+                .width(Length::FillPortion(4)),
+        );
+        let action = match self.status {
+            Status::Queued | Status::Downloading(_) | Status::Retrying(_) => {
+                Some(("pause", Message::PauseDownload(key)))
+            }
+            Status::Paused => Some(("resume", Message::ResumeDownload(key))),
+            Status::Failed(_) => Some(("retry", Message::RetryDownload(key))),
+            Status::Finished => None,
+        };
+        if let Some((text, msg)) = action {
+            row = row.push(small_button(&mut self.action_button, text, msg));
+            let cancel = Message::CancelDownload(key);
+            row = row.push(small_button(&mut self.cancel_button, "cancel", cancel));
+        }
+        row
+    }
+}
+
+/// Downloads view, lists queued, active, failed and finished downloads
+#[derive(Debug)]
+pub struct Downloads {
+    db: PodcastDb,
+    list: Vec<ListItem>,
+    scroll_state: scrollable::State,
+    // number of rows we scrolled down
+    scrolled_down: usize,
+}
+
+impl Downloads {
+    const MAXSCROLLABLE: usize = 10;
+    /// takes the downloads restored from the queue and whether they are paused
+    pub fn from_db(db: PodcastDb, queued: impl Iterator<Item = (EpisodeKey, bool)>) -> Self {
+        let mut page = Self {
+            db,
+            list: Vec::new(),
+            scroll_state: scrollable::State::new(),
+            scrolled_down: 0,
+        };
+        for (key, paused) in queued {
+            page.add(key);
+            if paused {
+                page.set_status(key, Status::Paused);
+            }
+        }
+        page
+    }
+    pub fn down(&mut self) {
+        self.scrolled_down += Self::MAXSCROLLABLE;
+        self.scrolled_down = self.scrolled_down.min(self.list.len());
+    }
+    pub fn up(&mut self) {
+        self.scrolled_down = self.scrolled_down.saturating_sub(Self::MAXSCROLLABLE);
+    }
+    fn get_mut(&mut self, key: EpisodeKey) -> Option<&mut ListItem> {
+        self.list.iter_mut().find(|item| item.key == key)
+    }
+    fn set_status(&mut self, key: EpisodeKey, status: Status) {
+        if let Some(item) = self.get_mut(key) {
+            item.status = status;
+            item.speed.reset();
+        }
+    }
+    /// adds a download or marks it queued again if it was already listed
+    pub fn add(&mut self, key: EpisodeKey) {
+        if self.get_mut(key).is_some() {
+            return self.set_status(key, Status::Queued);
+        }
+        let title = match self.db.get_episode_ext(key) {
+            Ok(episode) => episode.title,
+            Err(_) => return,
+        };
+        self.list.push(ListItem::new(key, title, Status::Queued));
+    }
+    pub fn update(&mut self, key: EpisodeKey, progress: &download::Progress) {
+        use download::Progress;
+        let item = match self.get_mut(key) {
+            Some(item) => item,
+            None => return,
+        };
+        match progress {
+            Progress::Started => item.status = Status::Downloading(0.),
+            Progress::Advanced(p, downloaded) => {
+                item.status = Status::Downloading(*p);
+                item.speed.update(*downloaded);
+            }
+            Progress::Retrying(n) => {
+                item.status = Status::Retrying(*n);
+                item.speed.reset();
+            }
+            Progress::Finished => item.status = Status::Finished,
+            Progress::Error(e) => item.status = Status::Failed(e.to_string()),
+        }
+    }
+    pub fn paused(&mut self, key: EpisodeKey) {
+        self.set_status(key, Status::Paused);
+    }
+    pub fn queued(&mut self, key: EpisodeKey) {
+        self.set_status(key, Status::Queued);
+    }
+    pub fn remove(&mut self, key: EpisodeKey) {
+        self.list.retain(|item| item.key != key);
+    }
+    pub fn view(&mut self) -> Element<crate::Message> {
+        let mut scrollable = Scrollable::new(&mut self.scroll_state)
+            .padding(10)
+            .height(iced::Length::Fill);
+        if self.list.is_empty() {
+            scrollable = scrollable.push(Text::new("no downloads"));
+        }
+        for item in self
+            .list
+            .iter_mut()
+            .skip(self.scrolled_down)
+            .take(Self::MAXSCROLLABLE)
+        {
+            scrollable = scrollable.push(item.view());
+        }
+        scrollable.into()
+    }
+}
@@ -1,18 +1,22 @@
 use iced::widget::scrollable::{self, Scrollable};
 use iced::Length;
-use iced::{button, Button, Element, HorizontalAlignment, Row, Text};
+use iced::{button, Button, Column, Element, HorizontalAlignment, Row, Text};
 
+use super::small_button;
 use crate::database::Progress;
-use crate::database::{Episode, PodcastDb};
+use crate::database::{Episode, PodcastDb, Settings};
 use crate::database::{EpisodeKey, PodcastKey};
-use crate::download::{hash, FileType};
+use crate::download::FileType;
 use std::collections::HashMap;
 
 #[derive(Debug)]
 struct ListItem {
+    key: EpisodeKey,
     // either download or delete
     file_button: button::State,
     play_button: button::State,
+    next_button: button::State,
+    last_button: button::State,
     progress: Progress,
     file: Option<FileType>,
     title: String,
@@ -20,16 +24,23 @@ struct ListItem {
 }
 
 impl ListItem {
-    fn from(episode: Episode, episodes_on_disk: &HashMap<u64, FileType>) -> Self {
+    fn from(
+        key: EpisodeKey,
+        episode: Episode,
+        episodes_on_disk: &HashMap<EpisodeKey, FileType>,
+    ) -> Self {
         let title = episode.title.to_owned();
         let progress = episode.progress;
-        let file = episodes_on_disk.get(&hash(&title)).copied();
+        let file = episodes_on_disk.get(&key).copied();
 
         let date = episode.date.format();
 
         ListItem {
+            key,
             file_button: button::State::new(),
             play_button: button::State::new(),
+            next_button: button::State::new(),
+            last_button: button::State::new(),
             progress,
             file, // is none if no file was found
             title,
@@ -42,22 +53,24 @@ impl ListItem {
 #[derive(Debug)]
 pub struct Episodes {
     db: PodcastDb,
+    settings: Settings,
     list: Vec<ListItem>,
+    speed_button: button::State,
     scroll_state: scrollable::State,
-    pub podcast: Option<String>,
-    podcast_id: Option<PodcastKey>,
+    pub podcast_id: Option<PodcastKey>,
     // number of rows we scrolled down
     scrolled_down: usize,
 }
 
 impl Episodes {
     const MAXSCROLLABLE: usize = 10;
-    pub fn from_db(db: PodcastDb) -> Self {
+    pub fn from_db(db: PodcastDb, settings: Settings) -> Self {
         Self {
             db,
+            settings,
             list: Vec::new(),
+            speed_button: button::State::new(),
             scroll_state: scrollable::State::new(),
-            podcast: None,
             podcast_id: None,
             scrolled_down: 0,
         }
@@ -69,34 +82,58 @@ impl Episodes {
     pub fn up(&mut self) {
         self.scrolled_down = self.scrolled_down.saturating_sub(Self::MAXSCROLLABLE);
     }
-    pub fn repopulate(&mut self, downloaded_episodes: HashMap<u64, FileType>) {
+    pub fn repopulate(&mut self, downloaded_episodes: HashMap<EpisodeKey, FileType>) {
         let podcast_id = self.podcast_id.unwrap();
         self.list.clear();
-        self.podcast = Some(self.db.get_podcast(podcast_id).unwrap().title);
 
         let mut episodes = self.db.get_episodes(podcast_id).unwrap();
-        episodes.sort_unstable_by_key(|e| *e.date.inner());
+        episodes.sort_unstable_by_key(|(_, e)| *e.date.inner());
         episodes.reverse();
-        for info in episodes {
-            self.list.push(ListItem::from(info, &downloaded_episodes));
+        for (key, info) in episodes {
+            self.list
+                .push(ListItem::from(key, info, &downloaded_episodes));
         }
     }
     /// fill the view from a list of episodes
     pub fn populate(
         &mut self,
         podcast_id: PodcastKey,
-        downloaded_episodes: HashMap<u64, FileType>,
+        downloaded_episodes: HashMap<EpisodeKey, FileType>,
     ) {
         self.podcast_id = Some(podcast_id);
         self.repopulate(downloaded_episodes);
     }
-    pub fn update_downloaded(&mut self, downloaded_episodes: HashMap<u64, FileType>) {
+    pub fn set_progress(&mut self, key: EpisodeKey, progress: Progress) {
+        if let Some(item) = self.list.iter_mut().find(|i| i.key == key) {
+            item.progress = progress;
+        }
+    }
+    pub fn update_downloaded(&mut self, downloaded_episodes: HashMap<EpisodeKey, FileType>) {
         for item in &mut self.list {
-            let file = downloaded_episodes.get(&hash(&item.title)).copied();
+            let file = downloaded_episodes.get(&item.key).copied();
             item.file = file;
         }
     }
     pub fn view(&mut self) -> Element<crate::Message> {
+        let speed = self
+            .podcast_id
+            .and_then(|id| self.settings.podcast_speed(id))
+            .map(|s| format!("{}x", s))
+            .unwrap_or_else(|| "default".to_owned());
+        let mut header = Row::new().push(
+            Text::new("playback speed")
+                .width(Length::FillPortion(4))
+                .horizontal_alignment(HorizontalAlignment::Left),
+        );
+        if let Some(id) = self.podcast_id {
+            header = header.push(
+                Button::new(&mut self.speed_button, Text::new(speed))
+                    .on_press(crate::Message::CyclePodcastSpeed(id))
+                    .padding(12)
+                    .width(Length::FillPortion(1)),
+            );
+        }
+
         let mut scrollable = Scrollable::new(&mut self.scroll_state)
             .padding(10)
             .height(iced::Length::Fill);
@@ -106,8 +143,7 @@ impl Episodes {
             .skip(self.scrolled_down)
             .take(Self::MAXSCROLLABLE)
         {
-            let podcast_id = *self.podcast_id.as_ref().unwrap();
-            let key = EpisodeKey::from_title(podcast_id, &item.title);
+            let key = item.key;
             let mut row = Row::new();
             if let Some(file_type) = item.file {
                 row = row.push(play_button(
@@ -123,12 +159,36 @@ impl Episodes {
                     &mut item.play_button,
                     key,
                     item.title.clone(),
+                    item.progress,
                 ));
                 row = row.push(download_button(&mut item.file_button, key));
             }
+            let next = crate::Message::QueueNext(key);
+            row = row.push(small_button(&mut item.next_button, "next", next));
+            let last = crate::Message::QueueLast(key);
+            row = row.push(small_button(&mut item.last_button, "last", last));
             scrollable = scrollable.push(row);
         }
-        scrollable.into()
+        Column::new().push(header).push(scrollable).into()
+    }
+}
+
+/// the episode name followed by where playback resumes if it was started
+/// or a mark if it was played
+fn label(episode_name: String, progress: Progress) -> String {
+    let pos = match progress {
+        Progress::Listening(pos) if pos >= 1. => pos as u64,
+        Progress::Completed => return format!("{} (played)", episode_name),
+        _ => return episode_name,
+    };
+    let (hours, minutes, seconds) = (pos / 3600, pos / 60 % 60, pos % 60);
+    if hours > 0 {
+        format!(
+            "{} (at {}:{:02}:{:02})",
+            episode_name, hours, minutes, seconds
+        )
+    } else {
+        format!("{} (at {}:{:02})", episode_name, minutes, seconds)
     }
 }
 
@@ -142,7 +202,7 @@ fn play_button(
     let msg = crate::Message::Play(key, file_type, progress.into());
     Button::new(
         state,
-        Text::new(episode_name).horizontal_alignment(HorizontalAlignment::Left),
+        Text::new(label(episode_name, progress)).horizontal_alignment(HorizontalAlignment::Left),
     )
     .on_press(msg)
     .padding(12)
@@ -153,11 +213,12 @@ fn stream_button(
     state: &mut button::State,
     key: EpisodeKey,
     episode_name: String,
+    progress: Progress,
 ) -> Button<crate::Message> {
-    let msg = crate::Message::Stream(key);
+    let msg = crate::Message::Stream(key, progress.into());
     Button::new(
         state,
-        Text::new(episode_name).horizontal_alignment(HorizontalAlignment::Left),
+        Text::new(label(episode_name, progress)).horizontal_alignment(HorizontalAlignment::Left),
     )
     .on_press(msg)
     .padding(12)
@@ -1,17 +1,26 @@
+pub mod downloads;
 pub mod episodes;
 mod errorpage;
 pub mod podcasts;
+pub mod queue;
+pub mod shortcuts;
 
 use crate::Message;
+pub use downloads::Downloads;
 pub use episodes::Episodes;
-use iced::{button, Button, Element, Length, Row, Text};
+use iced::{button, Button, Element, HorizontalAlignment, Length, Row, Text};
 pub use podcasts::Podcasts;
+pub use queue::Queue;
+pub use shortcuts::Shortcuts;
 
 #[derive(Default)]
 pub struct Controls {
     pub back: button::State,
     pub up: button::State,
     pub down: button::State,
+    pub downloads: button::State,
+    pub queue: button::State,
+    pub shortcuts: button::State,
 }
 
 impl Controls {
@@ -32,14 +41,43 @@ impl Controls {
                 .on_press(Message::Down)
                 .width(Length::Fill),
         );
+        let row = row.push(
+            Button::new(&mut self.downloads, Text::new("downloads".to_owned()))
+                .on_press(Message::ToDownloads)
+                .width(Length::Fill),
+        );
+        let row = row.push(
+            Button::new(&mut self.queue, Text::new("queue".to_owned()))
+                .on_press(Message::ToQueue)
+                .width(Length::Fill),
+        );
+        let row = row.push(
+            Button::new(&mut self.shortcuts, Text::new("keys".to_owned()))
+                .on_press(Message::ToShortcuts)
+                .width(Length::Fill),
+        );
         row.into()
     }
 }
 
+/// a button sized to share a list item row with others
+fn small_button<'a>(state: &'a mut button::State, text: &str, msg: Message) -> Button<'a, Message> {
+    Button::new(
+        state,
+        Text::new(text).horizontal_alignment(HorizontalAlignment::Center),
+    )
+    .on_press(msg)
+    .padding(12)
+    .width(Length::FillPortion(1))
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Page {
     Podcasts,
     Episodes,
+    Downloads,
+    Queue,
+    Shortcuts,
 }
 
 impl Page {
@@ -47,6 +85,9 @@ impl Page {
         *self = match &self {
             Self::Podcasts => Self::Podcasts,
             Self::Episodes => Self::Podcasts,
+            Self::Downloads => Self::Podcasts,
+            Self::Queue => Self::Podcasts,
+            Self::Shortcuts => Self::Podcasts,
         }
     }
 }
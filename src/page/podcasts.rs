@@ -1,12 +1,15 @@
 use iced::Length;
-use iced::{button, Button, Column, Command, Element, HorizontalAlignment, Text};
+use iced::{button, Button, Column, Command, Element, HorizontalAlignment, Row, Text};
 use iced::{scrollable, Scrollable};
 use iced::{text_input, TextInput};
 use itertools::izip;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use super::small_button;
 use crate::database::{self, PodcastDb, PodcastKey};
+use crate::feed::opml;
 use crate::{feed, Message};
 
 #[derive(Default)]
@@ -30,9 +33,10 @@ impl Search {
         self.input_value = input;
         if feed::valid_url(&self.input_value) {
             let url = self.input_value.clone();
-            Command::perform(feed::add_podcast(pod_db, url), |(title, id)| {
-                Message::AddedPodcast(title, id)
-            })
+            Command::perform(feed::add_podcast(pod_db, url), Message::added_podcast)
+        } else if is_opml_file(&self.input_value) {
+            let path = PathBuf::from(&self.input_value);
+            Command::perform(opml::import(pod_db, path), Message::imported_opml)
         } else if self.input_value.len() > 4 {
             self.do_search(false)
         } else {
@@ -42,7 +46,7 @@ impl Search {
     pub fn view(&mut self) -> TextInput<crate::Message> {
         TextInput::new(
             &mut self.input,
-            "Add podcast url",
+            "Add podcast url or opml file",
             &self.input_value,
             Message::SearchInputChanged,
         )
@@ -54,10 +58,58 @@ impl Search {
     }
 }
 
+fn is_opml_file(input: &str) -> bool {
+    let path = std::path::Path::new(input);
+    path.extension().map(|e| e == "opml").unwrap_or(false) && path.is_file()
+}
+
+/// asks the user to confirm before a podcast is removed
+struct ConfirmRemove {
+    id: PodcastKey,
+    remove: button::State,
+    remove_with_files: button::State,
+    cancel: button::State,
+}
+
+impl ConfirmRemove {
+    fn new(id: PodcastKey) -> Self {
+        Self {
+            id,
+            remove: button::State::new(),
+            remove_with_files: button::State::new(),
+            cancel: button::State::new(),
+        }
+    }
+    fn view(&mut self, name: &str) -> Row<crate::Message> {
+        let id = self.id;
+        Row::new()
+            .push(Text::new(format!("remove {}?", name)).width(Length::FillPortion(2)))
+            .push(
+                Button::new(&mut self.remove, Text::new("remove"))
+                    .on_press(Message::ConfirmRemovePodcast(id, false))
+                    .width(Length::FillPortion(1)),
+            )
+            .push(
+                Button::new(&mut self.remove_with_files, Text::new("+ files"))
+                    .on_press(Message::ConfirmRemovePodcast(id, true))
+                    .width(Length::FillPortion(1)),
+            )
+            .push(
+                Button::new(&mut self.cancel, Text::new("cancel"))
+                    .on_press(Message::CancelRemovePodcast)
+                    .width(Length::FillPortion(1)),
+            )
+    }
+}
+
 #[derive(Default)]
 pub struct List {
     podcast_buttons: Vec<(PodcastKey, button::State)>,
     podcast_names: Vec<String>,
+    remove_buttons: Vec<button::State>,
+    confirm_remove: Option<ConfirmRemove>,
+    /// feeds from the last import or add that could not be added
+    errors: Vec<String>,
     feedres_buttons: Vec<button::State>,
     feedres_info: Vec<feed::SearchResult>,
     scroll_state: scrollable::State,
@@ -86,7 +138,7 @@ fn podcast_button(
     //Todo replace content of ToEpisode with some key
     .on_press(crate::Message::ToEpisodes(id))
     .padding(12)
-    .width(Length::Fill)
+    .width(Length::FillPortion(4))
 }
 
 impl List {
@@ -94,6 +146,9 @@ impl List {
         let mut scrollable = Scrollable::new(&mut self.scroll_state)
             .padding(10)
             .height(iced::Length::Fill);
+        for error in &self.errors {
+            scrollable = scrollable.push(Text::new(error.as_str()));
+        }
         for (button, info) in self
             .feedres_buttons
             .iter_mut()
@@ -101,12 +156,22 @@ impl List {
         {
             scrollable = scrollable.push(feedres_button(button, info.clone()));
         }
-        let valid_names = self
-            .podcast_names
-            .iter()
-            .filter(|n| n.contains(search_term));
-        for ((id, button), name) in izip!(self.podcast_buttons.iter_mut(), valid_names) {
-            scrollable = scrollable.push(podcast_button(button, name.to_owned(), *id));
+        let podcasts = izip!(
+            self.podcast_buttons.iter_mut(),
+            self.remove_buttons.iter_mut(),
+            self.podcast_names.iter()
+        )
+        .filter(|(_, _, n)| n.contains(search_term));
+        let mut confirm = self.confirm_remove.as_mut();
+        for ((id, button), rm_button, name) in podcasts {
+            let row = Row::new()
+                .push(podcast_button(button, name.to_owned(), *id))
+                .push(small_button(rm_button, "rm", Message::RemovePodcast(*id)));
+            scrollable = scrollable.push(row);
+            if confirm.as_ref().map(|c| c.id == *id).unwrap_or(false) {
+                let confirm = confirm.take().unwrap();
+                scrollable = scrollable.push(confirm.view(name));
+            }
         }
         scrollable
     }
@@ -132,9 +197,42 @@ impl List {
     pub fn remove_feedres(&mut self) {
         self.feedres_info.clear();
     }
+    /// a podcast that is already listed only gets its title updated
     pub fn add(&mut self, title: String, id: PodcastKey) {
+        if let Some(idx) = self.podcast_buttons.iter().position(|(k, _)| *k == id) {
+            self.podcast_names[idx] = title;
+            return;
+        }
         self.podcast_names.push(title);
         self.podcast_buttons.push((id, button::State::new()));
+        self.remove_buttons.push(button::State::new());
+    }
+    pub fn confirm_remove(&mut self, id: PodcastKey) {
+        self.confirm_remove = Some(ConfirmRemove::new(id));
+    }
+    pub fn cancel_remove(&mut self) {
+        self.confirm_remove = None;
+    }
+    pub fn remove(&mut self, id: PodcastKey) {
+        self.confirm_remove = None;
+        if let Some(idx) = self.podcast_buttons.iter().position(|(k, _)| *k == id) {
+            self.podcast_buttons.remove(idx);
+            self.podcast_names.remove(idx);
+            self.remove_buttons.remove(idx);
+        }
+    }
+    pub fn add_imported(&mut self, report: Vec<opml::ImportResult>) {
+        self.errors.clear();
+        for opml::ImportResult { url, outcome } in report {
+            match outcome {
+                Ok((title, id)) => self.add(title, id),
+                Err(e) => self.errors.push(format!("could not import {}: {}", url, e)),
+            }
+        }
+    }
+    pub fn show_error(&mut self, error: String) {
+        self.errors.clear();
+        self.errors.push(error);
     }
 }
 
@@ -142,6 +240,7 @@ pub struct Podcasts {
     /// the podcasts title
     pub list: List,
     pub search: Search,
+    export_button: button::State,
     podcasts: database::PodcastDb,
     // possible opt to do, cache the view
 }
@@ -151,12 +250,11 @@ impl Podcasts {
         let mut page = Podcasts {
             list: List::default(),
             search: Search::default(),
+            export_button: button::State::new(),
             podcasts: db,
         };
-        for database::Podcast { title, .. } in page.podcasts.get_podcasts().unwrap() {
-            let id = PodcastKey::from(title.as_str());
-            page.list.podcast_names.push(title);
-            page.list.podcast_buttons.push((id, button::State::new()));
+        for (id, database::Podcast { title, .. }) in page.podcasts.get_podcasts().unwrap() {
+            page.list.add(title, id);
         }
         page
     }
@@ -169,8 +267,12 @@ impl Podcasts {
     pub fn view(&mut self) -> Element<crate::Message> {
         let scrollable = self.list.view(&self.search.input_value);
         let searchbar = self.search.view();
+        let export = Button::new(&mut self.export_button, Text::new("export"))
+            .on_press(Message::ExportOpml)
+            .padding(12);
+        let top = Row::new().push(searchbar).push(export);
 
-        let column = Column::new().push(searchbar).push(scrollable);
+        let column = Column::new().push(top).push(scrollable);
         column.into()
     }
 }
@@ -0,0 +1,176 @@
+use iced::widget::scrollable::{self, Scrollable};
+use iced::Length;
+use iced::{button, Button, Column, Element, HorizontalAlignment, Row, Text};
+
+use super::small_button;
+use crate::database::{EpisodeKey, PlayQueue, PodcastDb};
+use crate::Message;
+
+#[derive(Debug)]
+struct ListItem {
+    key: EpisodeKey,
+    title: String,
+    play_button: button::State,
+    up_button: button::State,
+    down_button: button::State,
+    remove_button: button::State,
+}
+
+impl ListItem {
+    fn new(key: EpisodeKey, title: String) -> Self {
+        Self {
+            key,
+            title,
+            play_button: button::State::new(),
+            up_button: button::State::new(),
+            down_button: button::State::new(),
+            remove_button: button::State::new(),
+        }
+    }
+    fn view(&mut self) -> Row<Message> {
+        let key = self.key;
+        let play = Button::new(
+            &mut self.play_button,
+            Text::new(self.title.clone()).horizontal_alignment(HorizontalAlignment::Left),
+        )
+        .on_press(Message::PlayQueued(key))
+        .padding(12)
+        .width(Length::FillPortion(4));
+        Row::new()
+            .push(play)
+            .push(small_button(
+                &mut self.up_button,
+                "up",
+                Message::QueueMove(key, -1),
+            ))
+            .push(small_button(
+                &mut self.down_button,
+                "down",
+                Message::QueueMove(key, 1),
+            ))
+            .push(small_button(
+                &mut self.remove_button,
+                "rm",
+                Message::QueueRemove(key),
+            ))
+    }
+}
+
+/// Up next view, the episodes that play after the current one
+#[derive(Debug)]
+pub struct Queue {
+    db: PodcastDb,
+    queue: PlayQueue,
+    list: Vec<ListItem>,
+    clear_button: button::State,
+    scroll_state: scrollable::State,
+    // number of rows we scrolled down
+    scrolled_down: usize,
+}
+
+impl Queue {
+    const MAXSCROLLABLE: usize = 10;
+    pub fn from_db(db: PodcastDb, queue: PlayQueue) -> Self {
+        let mut page = Self {
+            db,
+            queue,
+            list: Vec::new(),
+            clear_button: button::State::new(),
+            scroll_state: scrollable::State::new(),
+            scrolled_down: 0,
+        };
+        page.repopulate();
+        page
+    }
+    pub fn down(&mut self) {
+        self.scrolled_down += Self::MAXSCROLLABLE;
+        self.scrolled_down = self.scrolled_down.min(self.list.len());
+    }
+    pub fn up(&mut self) {
+        self.scrolled_down = self.scrolled_down.saturating_sub(Self::MAXSCROLLABLE);
+    }
+    /// rebuild the list from the database, drops episodes that no
+    /// longer exist (their podcast was removed)
+    pub fn repopulate(&mut self) {
+        let keys = self.queue.get_all().unwrap_or_else(|e| {
+            log::error!("could not read play queue: {}", e);
+            Vec::new()
+        });
+        self.list.clear();
+        for key in keys {
+            match self.db.get_episode(key) {
+                Ok(episode) => self.list.push(ListItem::new(key, episode.title)),
+                Err(_) => {
+                    let _ = self.queue.remove(key);
+                }
+            }
+        }
+        self.scrolled_down = self.scrolled_down.min(self.list.len());
+    }
+    fn apply(&mut self, res: Result<(), crate::database::Error>) {
+        if let Err(e) = res {
+            log::error!("could not update play queue: {}", e);
+        }
+        self.repopulate();
+    }
+    pub fn play_next(&mut self, key: EpisodeKey) {
+        let res = self.queue.push_front(key);
+        self.apply(res);
+    }
+    pub fn play_last(&mut self, key: EpisodeKey) {
+        let res = self.queue.push_back(key);
+        self.apply(res);
+    }
+    pub fn shift(&mut self, key: EpisodeKey, by: i32) {
+        let res = self.queue.shift(key, by);
+        self.apply(res);
+    }
+    pub fn remove(&mut self, key: EpisodeKey) {
+        if self.list.iter().any(|item| item.key == key) {
+            let res = self.queue.remove(key);
+            self.apply(res);
+        }
+    }
+    pub fn clear(&mut self) {
+        let res = self.queue.clear();
+        self.apply(res);
+    }
+    /// takes the episode that should play next off the queue
+    pub fn pop_front(&mut self) -> Option<EpisodeKey> {
+        let next = self.queue.pop_front().unwrap_or_else(|e| {
+            log::error!("could not read play queue: {}", e);
+            None
+        });
+        self.repopulate();
+        next
+    }
+    pub fn view(&mut self) -> Element<crate::Message> {
+        let header = Row::new()
+            .push(
+                Text::new("up next")
+                    .width(Length::FillPortion(4))
+                    .horizontal_alignment(HorizontalAlignment::Left),
+            )
+            .push(small_button(
+                &mut self.clear_button,
+                "clear",
+                Message::QueueClear,
+            ));
+
+        let mut scrollable = Scrollable::new(&mut self.scroll_state)
+            .padding(10)
+            .height(iced::Length::Fill);
+        if self.list.is_empty() {
+            scrollable = scrollable.push(Text::new("nothing queued"));
+        }
+        for item in self
+            .list
+            .iter_mut()
+            .skip(self.scrolled_down)
+            .take(Self::MAXSCROLLABLE)
+        {
+            scrollable = scrollable.push(item.view());
+        }
+        Column::new().push(header).push(scrollable).into()
+    }
+}
@@ -0,0 +1,83 @@
+use iced::Length;
+use iced::{button, Button, Column, Element, HorizontalAlignment, Row, Text};
+
+use crate::database::{Settings, Shortcut};
+use crate::Message;
+
+#[derive(Debug)]
+struct ListItem {
+    shortcut: Shortcut,
+    button: button::State,
+}
+
+/// Keyboard shortcuts view, click a shortcut then press the key to bind
+#[derive(Debug)]
+pub struct Shortcuts {
+    settings: Settings,
+    list: Vec<ListItem>,
+    /// the shortcut the next key press is bound to
+    waiting: Option<Shortcut>,
+}
+
+impl Shortcuts {
+    pub fn from_settings(settings: Settings) -> Self {
+        let list = Shortcut::ALL
+            .iter()
+            .map(|shortcut| ListItem {
+                shortcut: *shortcut,
+                button: button::State::new(),
+            })
+            .collect();
+        Self {
+            settings,
+            list,
+            waiting: None,
+        }
+    }
+    pub fn rebind(&mut self, shortcut: Shortcut) {
+        self.waiting = Some(shortcut);
+    }
+    /// binds the key if waiting for one, returns if the key was used
+    pub fn key_pressed(&mut self, key: &str) -> bool {
+        let shortcut = match self.waiting.take() {
+            Some(shortcut) => shortcut,
+            None => return false,
+        };
+        if let Err(e) = self.settings.set_shortcut(shortcut, key) {
+            log::error!("could not store shortcut: {}", e);
+        }
+        true
+    }
+    pub fn view(&mut self) -> Element<crate::Message> {
+        let mut column = Column::new().padding(10);
+        for item in &mut self.list {
+            let key = if self.waiting == Some(item.shortcut) {
+                "press a key".to_owned()
+            } else {
+                let key = self.settings.shortcut(item.shortcut);
+                if key.is_empty() {
+                    "none".to_owned()
+                } else {
+                    key
+                }
+            };
+            let row = Row::new()
+                .push(
+                    Text::new(item.shortcut.description())
+                        .horizontal_alignment(HorizontalAlignment::Left)
+                        .width(Length::FillPortion(2)),
+                )
+                .push(
+                    Button::new(
+                        &mut item.button,
+                        Text::new(key).horizontal_alignment(HorizontalAlignment::Center),
+                    )
+                    .on_press(Message::RebindShortcut(item.shortcut))
+                    .padding(12)
+                    .width(Length::FillPortion(1)),
+                );
+            column = column.push(row);
+        }
+        column.height(Length::Fill).into()
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+/// seconds of audio buffered before a stream starts playing
+pub const START: f32 = 10.;
+/// seconds of audio buffered before playback continues after the
+/// buffer ran out, more then at the start to not run out again
+pub const RESUME: f32 = 30.;
+/// bytes at the start of a stream the decoder reads before it can
+/// seek, enough for the headers of most episodes
+pub const HEADER: u64 = 64 * 1024;
+/// playback pauses if less then this many seconds are buffered
+const LOW: f32 = 2.;
+/// seconds buffered that are enough if data arrives fast
+const MINIMUM: f32 = 4.;
+/// data arrives fast if it does this much faster then it plays
+const FAST: f32 = 1.5;
+/// bytes per second of audio assumed until it is measured (128 kbit/s)
+pub const DEFAULT_BITRATE: f32 = 16_000.;
+
+/// download speed of the stream, measured over windows of a second
+#[derive(Debug, Default)]
+pub struct Throughput {
+    window_start: Option<Instant>,
+    bytes: u64,
+    rate: Option<f32>,
+}
+
+impl Throughput {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    pub fn add(&mut self, bytes: u64, now: Instant) {
+        let start = *self.window_start.get_or_insert(now);
+        self.bytes += bytes;
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed < Self::WINDOW {
+            return;
+        }
+        let rate = self.bytes as f32 / elapsed.as_secs_f32();
+        self.rate = Some(self.rate.map(|r| (r + rate) / 2.).unwrap_or(rate));
+        self.window_start = Some(now);
+        self.bytes = 0;
+    }
+    /// the fetcher was not fetching for a while, do not count that
+    /// time in the current window
+    pub fn pause(&mut self) {
+        self.window_start = None;
+        self.bytes = 0;
+    }
+    /// bytes per second, none until measured
+    pub fn get(&self) -> Option<f32> {
+        self.rate
+    }
+}
+
+/// bytes of stream per second of audio, measured while playing
+#[derive(Debug, Default)]
+pub struct Bitrate {
+    bytes: u64,
+    secs: f32,
+    /// last reader offset and playback position
+    last: Option<(u64, f32)>,
+}
+
+impl Bitrate {
+    /// playback steps larger then this are seeks
+    const MAX_STEP: f32 = 2.;
+    /// seconds of audio played before the measurement is used
+    const MEASURED: f32 = 10.;
+
+    pub fn sample(&mut self, offset: u64, pos: f32) {
+        if let Some((last_offset, last_pos)) = self.last {
+            let step = pos - last_pos;
+            if offset >= last_offset && step > 0. && step < Self::MAX_STEP {
+                self.bytes += offset - last_offset;
+                self.secs += step;
+            }
+        }
+        self.last = Some((offset, pos));
+    }
+    /// the next sample does not follow from the last, for example
+    /// because of a seek
+    pub fn skip_sample(&mut self) {
+        self.last = None;
+    }
+    pub fn get(&self) -> Option<f32> {
+        if self.secs < Self::MEASURED {
+            return None;
+        }
+        Some(self.bytes as f32 / self.secs)
+    }
+}
+
+/// how much of a stream is buffered ahead of playback
+#[derive(Debug, Clone, Copy)]
+pub struct Level {
+    /// bytes fetched directly after where the decoder reads
+    pub ahead: u64,
+    /// bytes per second of audio
+    pub bitrate: f32,
+    /// bytes fetched per second
+    pub throughput: Option<f32>,
+    /// everything up to the end is fetched
+    pub done: bool,
+}
+
+impl Level {
+    /// seconds of audio buffered
+    pub fn secs(&self) -> f32 {
+        self.ahead as f32 / self.bitrate
+    }
+    /// enough is buffered to play for target seconds, or data arrives
+    /// faster then it plays and a little is buffered
+    pub fn enough(&self, target: f32) -> bool {
+        let fast = matches!(self.throughput, Some(t) if t >= FAST * self.bitrate);
+        self.done || self.secs() >= target || (fast && self.secs() >= MINIMUM)
+    }
+    /// the buffer is (about to be) empty, playback should wait
+    pub fn underrun(&self) -> bool {
+        !self.done && self.secs() < LOW
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bitrate_ignores_seeks() {
+        let mut bitrate = Bitrate::default();
+        let mut offset = 0;
+        for tick in 0..=60 {
+            bitrate.sample(offset, tick as f32 / 4.);
+            offset += 4_000;
+        }
+        // a seek forward and back is not counted
+        bitrate.sample(10_000_000, 600.);
+        bitrate.sample(0, 0.);
+        assert_eq!(bitrate.get(), Some(16_000.));
+    }
+
+    #[test]
+    fn buffer_levels() {
+        let mut level = Level {
+            ahead: 16_000 * 5,
+            bitrate: 16_000.,
+            throughput: None,
+            done: false,
+        };
+        assert!(!level.enough(START));
+        assert!(!level.underrun());
+        level.throughput = Some(32_000.);
+        assert!(level.enough(START));
+
+        level.ahead = 16_000;
+        assert!(level.underrun());
+        level.done = true;
+        assert!(!level.underrun());
+        assert!(level.enough(RESUME));
+    }
+
+    #[test]
+    fn throughput_per_window() {
+        let start = Instant::now();
+        let mut throughput = Throughput::default();
+        throughput.add(1000, start);
+        assert_eq!(throughput.get(), None);
+        throughput.add(1000, start + Duration::from_secs(2));
+        assert_eq!(throughput.get(), Some(1000.));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::database::Chapter;
+use iced::{Length, Row, Rule, Space};
+
+/// skipping back within this many seconds of the start of a chapter
+/// goes to the chapter before
+const RESTART: f32 = 3.;
+/// chapters starting this close after the position are skipped over,
+/// otherwise the position rounding would keep us in the same place
+const MARGIN: f32 = 0.5;
+
+/// the chapter playing at pos
+pub fn current(chapters: &[Chapter], pos: f32) -> Option<&Chapter> {
+    chapters.iter().rev().find(|c| c.start <= pos)
+}
+
+/// start of the chapter after the one playing at pos
+pub fn next_start(chapters: &[Chapter], pos: f32) -> Option<f32> {
+    chapters
+        .iter()
+        .map(|c| c.start)
+        .find(|start| *start > pos + MARGIN)
+}
+
+/// start of the chapter playing at pos, or of the chapter before if
+/// it just started
+pub fn previous_start(chapters: &[Chapter], pos: f32) -> Option<f32> {
+    chapters
+        .iter()
+        .rev()
+        .map(|c| c.start)
+        .find(|start| *start <= pos - RESTART)
+        .or_else(|| chapters.first().map(|c| c.start))
+}
+
+/// a row as wide as the playback bar with a mark where each chapter starts
+pub fn markers<'a, M: 'a>(chapters: &[Chapter], duration: f32) -> Row<'a, M> {
+    let mut row = Row::new().height(Length::Units(8));
+    let mut last = 0f32;
+    let starts = chapters
+        .iter()
+        .map(|c| c.start)
+        .filter(|start| *start > 0. && *start < duration);
+    for start in starts {
+        let portion = (start - last).max(1.) as u16;
+        row = row
+            .push(Space::with_width(Length::FillPortion(portion)))
+            .push(Rule::vertical(1));
+        last = start;
+    }
+    let portion = (duration - last).max(1.) as u16;
+    row.push(Space::with_width(Length::FillPortion(portion)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chapters() -> Vec<Chapter> {
+        [0., 60., 120.]
+            .iter()
+            .map(|start| Chapter {
+                start: *start,
+                title: format!("at {}", start),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn navigate() {
+        let chapters = chapters();
+        assert_eq!(current(&chapters, 90.).unwrap().start, 60.);
+        assert_eq!(next_start(&chapters, 90.), Some(120.));
+        assert_eq!(next_start(&chapters, 119.8), None);
+        assert_eq!(previous_start(&chapters, 90.), Some(60.));
+        // just after the start goes back a chapter
+        assert_eq!(previous_start(&chapters, 61.), Some(0.));
+        assert_eq!(previous_start(&chapters, 1.), Some(0.));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::download::FileType;
+use rodio::source::SourceExt;
+use rodio::Source;
+use std::io::{Read, Seek};
+use std::time::Duration;
+
+mod demux;
+use demux::Container;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Could not decode audio: {0}")]
+    Decoder(#[from] rodio::decoder::DecoderError),
+    #[error("Could not read audio: {0}")]
+    Demux(#[from] symphonia::core::errors::Error),
+    #[error("Could not decode opus audio: {0}")]
+    Opus(#[from] opus::Error),
+    #[error("File contains no audio")]
+    NoAudio,
+    #[error("Opus audio with {0} channels is not supported")]
+    OpusChannels(usize),
+}
+
+/// rodio decodes mp3, ogg vorbis, flac and wav, the others are decoded
+/// by a demuxer (see: `demux`)
+pub enum Decoder<R>
+where
+    R: Read + Seek,
+{
+    Rodio(Box<rodio::Decoder<R>>),
+    Demux(demux::Decoder),
+}
+
+/// decoder for the given file type, if the type is unknown every
+/// format rodio supports is tried
+pub fn decoder<R>(reader: R, file_type: Option<FileType>) -> Result<Decoder<R>, Error>
+where
+    R: Read + Seek + Send + Sync + 'static,
+{
+    let decoder = match file_type {
+        Some(FileType::Mp3) => rodio::Decoder::new_mp3(reader)?,
+        Some(FileType::Ogg) => rodio::Decoder::new_vorbis(reader)?,
+        Some(FileType::Flac) => rodio::Decoder::new_flac(reader)?,
+        Some(FileType::Wav) => rodio::Decoder::new_wav(reader)?,
+        Some(FileType::M4a) => {
+            return Ok(Decoder::Demux(demux::Decoder::new(reader, Container::Mp4)?))
+        }
+        Some(FileType::Opus) => {
+            return Ok(Decoder::Demux(demux::Decoder::new(reader, Container::Ogg)?))
+        }
+        None => rodio::Decoder::new(reader)?,
+    };
+    Ok(Decoder::Rodio(Box::new(decoder)))
+}
+
+impl<R> Iterator for Decoder<R>
+where
+    R: Read + Seek,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        match self {
+            Decoder::Rodio(decoder) => decoder.next(),
+            Decoder::Demux(decoder) => decoder.next(),
+        }
+    }
+}
+
+impl<R> Source for Decoder<R>
+where
+    R: Read + Seek,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        match self {
+            Decoder::Rodio(decoder) => decoder.current_frame_len(),
+            Decoder::Demux(decoder) => decoder.current_frame_len(),
+        }
+    }
+    fn channels(&self) -> u16 {
+        match self {
+            Decoder::Rodio(decoder) => decoder.channels(),
+            Decoder::Demux(decoder) => decoder.channels(),
+        }
+    }
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Decoder::Rodio(decoder) => decoder.sample_rate(),
+            Decoder::Demux(decoder) => decoder.sample_rate(),
+        }
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        match self {
+            Decoder::Rodio(decoder) => decoder.total_duration(),
+            Decoder::Demux(decoder) => decoder.total_duration(),
+        }
+    }
+}
+
+impl<R> SourceExt for Decoder<R>
+where
+    R: Read + Seek,
+{
+    fn set_pos(&mut self, pos: f32) {
+        match self {
+            Decoder::Rodio(decoder) => decoder.set_pos(pos),
+            Decoder::Demux(decoder) => decoder.set_pos(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decode(fixture: &'static [u8], file_type: Option<FileType>) -> usize {
+        let decoder = decoder(Cursor::new(fixture), file_type).unwrap();
+        decoder.count()
+    }
+
+    #[test]
+    fn decode_fixtures() {
+        let mp3 = include_bytes!("fixtures/silence.mp3");
+        let flac = include_bytes!("fixtures/sine.flac");
+        let wav = include_bytes!("fixtures/sine.wav");
+        let ogg = include_bytes!("fixtures/sine.ogg");
+        let m4a = include_bytes!("fixtures/sine.m4a");
+        let opus = include_bytes!("fixtures/sine.opus");
+
+        assert!(decode(mp3, Some(FileType::Mp3)) > 0);
+        assert_eq!(decode(flac, Some(FileType::Flac)), 4608);
+        assert_eq!(decode(wav, Some(FileType::Wav)), 4608);
+        assert!(decode(ogg, Some(FileType::Ogg)) >= 4608);
+        // 8 aac frames, the fixture has no gapless info to trim them
+        assert_eq!(decode(m4a, Some(FileType::M4a)), 8192);
+        // 0.1 seconds at 48kHz, the pre-skip and padding are trimmed
+        assert_eq!(decode(opus, Some(FileType::Opus)), 4800);
+        // the type is detected if unknown
+        assert_eq!(decode(flac, None), 4608);
+    }
+
+    fn left_after_seek(fixture: &'static [u8], file_type: FileType, pos: f32) -> usize {
+        let mut decoder = decoder(Cursor::new(fixture), Some(file_type)).unwrap();
+        decoder.set_pos(pos);
+        decoder.count()
+    }
+
+    #[test]
+    fn seek_demuxed() {
+        let m4a = include_bytes!("fixtures/sine.m4a");
+        let opus = include_bytes!("fixtures/sine.opus");
+
+        assert_eq!(left_after_seek(m4a, FileType::M4a, 0.05), 8192 - 2205);
+        assert_eq!(left_after_seek(opus, FileType::Opus, 0.05), 4800 - 2400);
+    }
+
+    #[test]
+    fn opus_needs_an_opus_stream() {
+        let ogg = include_bytes!("fixtures/sine.ogg");
+        let res = decoder(Cursor::new(&ogg[..]), Some(FileType::Opus));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! m4a (aac) and ogg opus, which rodio can not decode. Symphonia reads
+//! the container, the audio is decoded by symphonia (aac) or libopus.
+
+use super::Error;
+use rodio::source::SourceExt;
+use rodio::Source;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{self, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::formats::{FormatOptions, FormatReader, Packet, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::units::{Time, TimeBase};
+use symphonia::default::formats::{IsoMp4Reader, OggReader};
+
+/// opus always decodes to 48kHz
+const OPUS_RATE: u32 = 48_000;
+/// longest opus packet is 120ms
+const MAX_OPUS_FRAMES: usize = 5760;
+
+pub enum Container {
+    Mp4,
+    Ogg,
+}
+
+struct Media<R> {
+    inner: R,
+    len: Option<u64>,
+}
+
+impl<R: Read> Read for Media<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for Media<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<R> MediaSource for Media<R>
+where
+    R: Read + Seek + Send + Sync,
+{
+    /// without the length the reader can not find the index of an
+    /// m4a file or seek in ogg
+    fn is_seekable(&self) -> bool {
+        self.len.is_some()
+    }
+    fn byte_len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+/// length of the media, not known for some streams
+fn byte_len<R: Seek>(reader: &mut R) -> Option<u64> {
+    let len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    Some(len)
+}
+
+enum Codec {
+    Symphonia(Box<dyn codecs::Decoder>),
+    Opus(opus::Decoder),
+}
+
+pub struct Decoder {
+    format: Box<dyn FormatReader>,
+    codec: Codec,
+    track: u32,
+    time_base: Option<TimeBase>,
+    /// set by the first packet for codecs other than opus
+    channels: u16,
+    sample_rate: u32,
+    /// opus encoder delay, symphonia leaves trimming it to the decoder
+    pre_skip: u64,
+    /// decoded interleaved samples of the current packet
+    buffer: Vec<i16>,
+    next: usize,
+    /// frames to drop after seeking to just before the target
+    skip: u64,
+}
+
+impl Decoder {
+    pub fn new<R>(mut reader: R, container: Container) -> Result<Self, Error>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        let len = byte_len(&mut reader);
+        let media = Media { inner: reader, len };
+        let stream = MediaSourceStream::new(Box::new(media), Default::default());
+        // trims the encoder delay and padding of gapless formats
+        let options = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let format: Box<dyn FormatReader> = match container {
+            Container::Mp4 => Box::new(IsoMp4Reader::try_new(stream, &options)?),
+            Container::Ogg => Box::new(OggReader::try_new(stream, &options)?),
+        };
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(Error::NoAudio)?;
+        let params = &track.codec_params;
+        let (codec, channels, pre_skip) = if params.codec == CODEC_TYPE_OPUS {
+            let channels = params.channels.ok_or(Error::NoAudio)?.count();
+            let opus_channels = match channels {
+                1 => opus::Channels::Mono,
+                2 => opus::Channels::Stereo,
+                n => return Err(Error::OpusChannels(n)),
+            };
+            let decoder = opus::Decoder::new(OPUS_RATE, opus_channels)?;
+            let pre_skip = params.delay.unwrap_or(0) as u64;
+            (Codec::Opus(decoder), channels as u16, pre_skip)
+        } else {
+            // the aac decoder knows the channels only once it decoded audio
+            let decoder =
+                symphonia::default::get_codecs().make(params, &DecoderOptions::default())?;
+            (Codec::Symphonia(decoder), 0, 0)
+        };
+
+        let mut decoder = Self {
+            track: track.id,
+            time_base: params.time_base,
+            channels,
+            sample_rate: OPUS_RATE,
+            pre_skip,
+            format,
+            codec,
+            buffer: Vec::new(),
+            next: 0,
+            skip: pre_skip,
+        };
+        if !decoder.decode_next() {
+            return Err(Error::NoAudio);
+        }
+        Ok(decoder)
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<(), Error> {
+        self.buffer.clear();
+        self.next = 0;
+        match &mut self.codec {
+            Codec::Symphonia(decoder) => {
+                let decoded = decoder.decode(packet)?;
+                let spec = *decoded.spec();
+                self.channels = spec.channels.count() as u16;
+                self.sample_rate = spec.rate;
+                let mut samples = SampleBuffer::new(decoded.capacity() as u64, spec);
+                samples.copy_interleaved_ref(decoded);
+                self.buffer.extend_from_slice(samples.samples());
+            }
+            Codec::Opus(decoder) => {
+                let channels = self.channels as usize;
+                self.buffer.resize(MAX_OPUS_FRAMES * channels, 0);
+                let frames = decoder.decode(&packet.data, &mut self.buffer, false)?;
+                self.buffer.truncate(frames * channels);
+            }
+        }
+
+        let channels = self.channels as usize;
+        let frames = (self.buffer.len() / channels) as u64;
+        let trim_start = packet.trim_start as u64;
+        let start = (trim_start + self.skip).min(frames);
+        // what is left is skipped in the next packet
+        self.skip -= start.saturating_sub(trim_start);
+        let end = frames.saturating_sub(packet.trim_end as u64).max(start);
+        self.buffer.truncate(end as usize * channels);
+        self.next = start as usize * channels;
+        Ok(())
+    }
+
+    /// decodes the next packet, false at the end of the audio
+    fn decode_next(&mut self) -> bool {
+        use symphonia::core::errors::Error as Symphonia;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Symphonia::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return false
+                }
+                Err(e) => {
+                    log::error!("could not read audio: {}", e);
+                    return false;
+                }
+            };
+            if packet.track_id() != self.track {
+                continue;
+            }
+            match self.decode(&packet) {
+                Ok(()) => return true,
+                // a damaged packet, the ones after might be fine
+                Err(Error::Demux(Symphonia::DecodeError(e))) => {
+                    log::warn!("skipping audio that can not be decoded: {}", e)
+                }
+                Err(Error::Opus(e)) => log::warn!("skipping audio that can not be decoded: {}", e),
+                Err(e) => {
+                    log::error!("could not decode audio: {}", e);
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        while self.next >= self.buffer.len() {
+            if !self.decode_next() {
+                return None;
+            }
+        }
+        let sample = self.buffer[self.next];
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Decoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len() - self.next)
+    }
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl SourceExt for Decoder {
+    fn set_pos(&mut self, pos: f32) {
+        let time_base = match self.time_base {
+            Some(base) => base,
+            None => return log::error!("can not seek, audio has no time base"),
+        };
+        let to = SeekTo::TimeStamp {
+            ts: time_base.calc_timestamp(Time::from(pos.max(0.) as f64)) + self.pre_skip,
+            track_id: self.track,
+        };
+        let seeked = match self.format.seek(SeekMode::Accurate, to) {
+            Ok(seeked) => seeked,
+            Err(e) => return log::error!("could not seek to {}s: {}", pos, e),
+        };
+        match &mut self.codec {
+            Codec::Symphonia(decoder) => decoder.reset(),
+            Codec::Opus(decoder) => {
+                if let Err(e) = decoder.reset_state() {
+                    log::error!("could not reset opus decoder: {}", e);
+                }
+            }
+        }
+        // the seek lands on a packet at or before the target
+        let ahead = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        let time = time_base.calc_time(ahead);
+        let secs = time.seconds as f64 + time.frac;
+        self.skip = (secs * self.sample_rate as f64).round() as u64;
+        self.buffer.clear();
+        self.next = 0;
+    }
+}
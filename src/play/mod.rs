@@ -4,24 +4,65 @@ use crate::download::FileType;
 use crate::Message;
 use iced::{button, Button, Column, Command, Length, Row, Space, Text};
 use std::io::BufReader;
-use std::sync::mpsc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+mod buffer;
+mod chapters;
+mod decode;
+mod position;
+mod sleep;
 mod stream;
-pub use stream::ReadableReciever;
+mod stretch;
+use position::{Counter, Position};
+use sleep::SleepTimer;
+pub use stream::Shared;
+use stream::StreamReader;
+use stretch::{Speed, Stretch};
 pub mod subscribe;
 
+/// playback speeds to cycle through
+const SPEEDS: [f32; 6] = [0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+
+/// the speed after this one, wraps around to the slowest
+pub fn next_speed(speed: f32) -> f32 {
+    SPEEDS
+        .iter()
+        .copied()
+        .find(|s| *s > speed + 0.01)
+        .unwrap_or(SPEEDS[0])
+}
+
+/// the next faster speed, does not wrap around
+pub fn faster(speed: f32) -> f32 {
+    SPEEDS
+        .iter()
+        .copied()
+        .find(|s| *s > speed + 0.01)
+        .unwrap_or(speed)
+}
+
+/// the next slower speed, does not wrap around
+pub fn slower(speed: f32) -> f32 {
+    SPEEDS
+        .iter()
+        .rev()
+        .copied()
+        .find(|s| *s < speed - 0.01)
+        .unwrap_or(speed)
+}
+
 type Url = String;
 type StreamPos = f32;
 pub enum Track {
-    Stream(TrackInfo, StreamPos, Url),
+    /// also holds the path the stream is cached at
+    Stream(TrackInfo, StreamPos, Url, std::path::PathBuf),
     File(TrackInfo, std::path::PathBuf),
     None,
 }
 
 impl Track {
     pub fn set_streampos(&mut self, new_pos: StreamPos) {
-        if let Track::Stream(_, pos, _) = self {
+        if let Track::Stream(_, pos, ..) = self {
             *pos = new_pos;
         } else {
             panic!("Track variant is not Stream")
@@ -34,9 +75,9 @@ impl Track {
             Track::None => None,
         }
     }
-    /// Duration in seconds
-    pub fn duration(&self) -> f32 {
-        self.info().map(|i| i.duration).unwrap_or(0f32)
+    /// Duration in seconds, if known
+    pub fn duration(&self) -> Option<f32> {
+        self.info().and_then(|i| i.duration)
     }
 }
 
@@ -44,7 +85,8 @@ pub struct TrackInfo {
     pub id: database::EpisodeKey,
     pub title: String,
     pub paused: bool,
-    pub duration: f32,
+    /// unknown for episodes the feed gives no duration for
+    pub duration: Option<f32>,
 }
 
 #[derive(Default)]
@@ -52,10 +94,16 @@ struct Controls {
     play_pauze: button::State,
     skip_forward: button::State,
     skip_backward: button::State,
+    speed: button::State,
+    end_action: button::State,
+    sleep: button::State,
+    extend_sleep: button::State,
+    previous_chapter: button::State,
+    next_chapter: button::State,
     skip_dur: f32,
 }
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 pub struct Player {
     controls: Controls,
     pub current: Track,
@@ -64,15 +112,31 @@ pub struct Player {
     pub output_stream: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
 
     db: database::PodcastDb,
-    pub rx: Option<Arc<Mutex<mpsc::Receiver<bytes::Bytes>>>>,
+    settings: database::Settings,
+    /// data of the stream being played
+    pub stream: Option<Arc<Shared>>,
+    /// type of the streamed audio if known
+    pub stream_type: Option<FileType>,
+    /// where to resume the stream once it starts
+    stream_start: f32,
+    stream_bitrate: buffer::Bitrate,
+    /// paused because the stream buffer ran out
+    buffering: bool,
+    /// chapters of the current episode sorted by start
+    chapters: Vec<database::Chapter>,
 
-    last_started: Option<Instant>,
     last_stored: Option<f32>,
-    offset: f32,
+    position: Position,
+    speed: Speed,
+    sleep: SleepTimer,
+    /// number of times the position was changed by the user
+    seeks: u32,
+    /// episodes played before the current one, most recent last
+    history: Vec<database::EpisodeKey>,
 }
 
 impl Player {
-    pub fn from_db(db: database::PodcastDb) -> Self {
+    pub fn from_db(db: database::PodcastDb, settings: database::Settings) -> Self {
         Self {
             controls: Controls {
                 skip_dur: 5f32,
@@ -82,50 +146,104 @@ impl Player {
             sink: None,
             output_stream: None,
             db,
-            rx: None,
-            last_started: None,
+            settings,
+            stream: None,
+            stream_type: None,
+            stream_start: 0f32,
+            stream_bitrate: buffer::Bitrate::default(),
+            buffering: false,
+            chapters: Vec::new(),
             last_stored: None,
-            offset: 0f32,
+            position: Position::new(),
+            speed: Speed::new(1.0),
+            sleep: SleepTimer::Off,
+            seeks: 0,
+            history: Vec::new(),
         }
     }
 
-    pub fn start_stream(&mut self) {
-        let rx = self.rx.take().unwrap();
-        let rx = Arc::try_unwrap(rx).unwrap();
-        let rx = rx.into_inner().unwrap();
-        let rrx = ReadableReciever::new(rx);
-        let source = rodio::Decoder::new_mp3(rrx).unwrap();
-        self.start_play(source);
+    fn start_stream(&mut self, shared: Arc<Shared>) {
+        let reader = StreamReader::new(shared);
+        match decode::decoder(reader, self.stream_type) {
+            Ok(source) => self.start_play(source),
+            Err(e) => {
+                log::error!("can not play stream: {}", e);
+                self.stream = None;
+                return;
+            }
+        }
+        if self.stream_start > 0. {
+            self.sink.as_mut().unwrap().set_pos(self.stream_start);
+            self.position.set(self.stream_start);
+        }
     }
 
     fn start_play<S>(&mut self, source: S)
     where
-        S: rodio::source::Source + Send + 'static,
+        S: rodio::source::Source<Item = i16> + Send + 'static,
         S: rodio::source::SourceExt + Send + 'static,
-        S::Item: rodio::Sample,
-        S::Item: Send,
     {
         let (stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
         let sink = rodio::Sink::try_new(&stream_handle).unwrap();
-        sink.append_seekable(source);
+        self.position.set(0f32);
+        let source = Counter::new(source, self.position.clone());
+        sink.append_seekable(Stretch::new(source, self.speed.clone()));
 
         self.sink = Some(sink);
         self.output_stream = Some((stream, stream_handle));
-        self.last_started = Some(std::time::Instant::now());
-        self.offset = 0f32;
     }
 
-    fn pos(&self) -> f32 {
-        let elapsed = self
-            .last_started
-            .map(|t| t.elapsed().as_secs_f32())
-            .unwrap_or(0f32);
-        self.offset + elapsed
+    /// position in the episode, counted from the audio that was played
+    pub fn pos(&self) -> f32 {
+        self.position.get()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.sink.as_ref().map(|s| !s.is_paused()).unwrap_or(false)
+    }
+
+    pub fn seeks(&self) -> u32 {
+        self.seeks
+    }
+
+    /// seconds skipped by the skip buttons
+    pub fn skip_dur(&self) -> f32 {
+        self.controls.skip_dur
     }
 
+    /// remember the current episode if another one starts playing
+    fn add_to_history(&mut self, next: database::EpisodeKey) {
+        if let Some(info) = self.current.info() {
+            if info.id != next {
+                self.history.push(info.id);
+            }
+        }
+    }
+
+    /// the episode played before the current one
+    pub fn previous_episode(&mut self) -> Option<database::EpisodeKey> {
+        self.history.pop()
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed.get()
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed.set(speed);
+    }
+
+    /// use the speed set for the podcast or the global speed
+    fn apply_speed_setting(&mut self, id: database::EpisodeKey) {
+        let speed = self.settings.speed(id.podcast());
+        self.set_speed(speed);
+    }
+
+    /// the position once it moved 5 seconds from the last stored one,
+    /// that includes skipping backwards
     pub fn should_store_pos(&mut self) -> Option<f32> {
         let pos = self.pos();
-        if pos > self.last_stored.unwrap_or(0f32) + 5f32 {
+        if (pos - self.last_stored.unwrap_or(0f32)).abs() > 5f32 {
             self.last_stored = Some(pos);
             Some(pos)
         } else {
@@ -133,59 +251,241 @@ impl Player {
         }
     }
 
+    /// playtime left in the current episode at the current speed
+    fn episode_left(&self) -> Option<Duration> {
+        let left = (self.current.duration()? - self.pos()).max(0.) / self.speed();
+        Some(Duration::from_secs_f32(left))
+    }
+
+    pub fn cycle_sleep_timer(&mut self) {
+        self.sleep = self.sleep.next(Instant::now());
+        self.apply_sleep_volume();
+    }
+
+    pub fn extend_sleep_timer(&mut self) {
+        self.sleep = self.sleep.extend(Instant::now(), self.episode_left());
+        self.apply_sleep_volume();
+    }
+
+    fn apply_sleep_volume(&mut self) {
+        let volume = self.sleep.volume(Instant::now(), self.episode_left());
+        if let Some(sink) = self.sink.as_mut() {
+            sink.set_volume(volume);
+        }
+    }
+
+    /// fades out as the sleep timer runs out, once it expires playback
+    /// is paused and the position stored
+    pub fn sleep_tick(&mut self) {
+        if !self.sleep.expired(Instant::now()) {
+            return self.apply_sleep_volume();
+        }
+        self.sleep = SleepTimer::Off;
+        if let Some(sink) = self.sink.as_mut() {
+            sink.pause();
+            sink.set_volume(1.0);
+        }
+        if let Some(info) = self.current.info() {
+            let pos = self.position.get();
+            self.db
+                .update_episode_progress(info.id, database::Progress::Listening(pos));
+            self.last_stored = Some(pos);
+        }
+    }
+
+    /// if the sleep timer was set to stop at the end of this episode,
+    /// resets the timer
+    pub fn sleep_at_end(&mut self) -> bool {
+        let at_end = self.sleep == SleepTimer::EndOfEpisode;
+        if at_end {
+            self.sleep = SleepTimer::Off;
+        }
+        at_end
+    }
+
+    /// the episode that just played to its end, playback stops
+    pub fn track_ended(&mut self) -> Option<database::EpisodeKey> {
+        let sink = self.sink.as_ref()?;
+        if !sink.empty() || sink.is_paused() {
+            return None;
+        }
+        self.stop();
+        self.last_stored = None;
+        self.current.info().map(|info| info.id)
+    }
+
     fn stop(&mut self) {
         self.sink.take();
+        self.stream = None;
+        self.buffering = false;
     }
 
-    pub fn add_stream(&mut self, id: database::EpisodeKey) {
+    /// stop playback and forget the current track
+    pub fn clear(&mut self) {
         self.stop();
+        self.current = Track::None;
+    }
 
+    pub fn add_stream(&mut self, id: database::EpisodeKey, starting_pos: f32) {
+        self.stop();
+        self.stream_start = starting_pos;
+        self.stream_bitrate = buffer::Bitrate::default();
+        // show where playback resumes while the stream starts
+        self.position.set(starting_pos);
+        self.last_stored = Some(starting_pos);
+        self.add_to_history(id);
+
+        self.apply_speed_setting(id);
         let meta = self.db.get_episode_ext(id).unwrap();
+        let path = match crate::download::stream_path(id, &meta) {
+            Some(path) => path,
+            None => {
+                log::error!("can not stream, invalid url: {}", meta.stream_url);
+                return;
+            }
+        };
+        // the server might correct this once the stream starts
+        self.stream_type = meta
+            .mime_type
+            .as_deref()
+            .and_then(FileType::from_mime)
+            .or_else(|| {
+                let url = reqwest::Url::parse(&meta.stream_url).ok()?;
+                FileType::from_url(&url)
+            });
+        self.chapters = meta.chapters;
         self.current = Track::Stream(
             TrackInfo {
                 id,
                 title: String::default(),
                 paused: false,
-                duration: dbg!(meta.duration),
+                duration: meta.known_duration(),
             },
             0f32,
             meta.stream_url,
+            path,
         );
     }
 
-    // TODO figure out better way to get extension into here
     pub fn add_file(&mut self, id: database::EpisodeKey, file_type: FileType, starting_pos: f32) {
         use crate::download::base_file_path;
         self.stop();
+        self.add_to_history(id);
 
+        self.apply_speed_setting(id);
         let episode = self.db.get_episode_ext(id).unwrap();
-        let mut path = base_file_path(&episode);
+        let mut path = base_file_path(id);
         path.set_extension(file_type.as_str());
 
         let file = std::fs::File::open(&path).unwrap();
-        let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
+        let source = match decode::decoder(BufReader::new(file), Some(file_type)) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("can not play {:?}: {}", path, e);
+                return;
+            }
+        };
         self.start_play(source);
         self.sink.as_mut().unwrap().set_pos(starting_pos);
-        self.offset = starting_pos;
+        self.position.set(starting_pos);
+        self.chapters = self.file_chapters(id, &episode, file_type, &path);
 
         self.current = Track::File(
             TrackInfo {
                 id,
                 title: String::default(),
                 paused: false,
-                duration: episode.duration,
+                duration: episode.known_duration(),
             },
             path,
         );
     }
 
-    pub fn stream_ready(&self, p: f32) -> bool {
-        const MINIMUM_BUF_DUR: f32 = 60f32; // duration (seconds) that needs to be downloaded before we start playing
-        let downloaded_duration = p / 100f32 * self.current.duration();
-        let sink_empty = self.sink.as_ref().map(|s| s.empty()).unwrap_or(true);
-        let downloaded_enough = downloaded_duration > MINIMUM_BUF_DUR;
+    /// the chapters from the feed, if there are none those embedded in
+    /// the file which are then stored with the episode
+    fn file_chapters(
+        &self,
+        id: database::EpisodeKey,
+        episode: &database::EpisodeExt,
+        file_type: FileType,
+        path: &std::path::Path,
+    ) -> Vec<database::Chapter> {
+        if !episode.chapters.is_empty() || file_type != FileType::Mp3 {
+            return episode.chapters.clone();
+        }
+        match crate::download::read_chapters(path) {
+            Ok(chapters) if chapters.is_empty() => chapters,
+            Ok(chapters) => {
+                if let Err(e) = self.db.set_chapters(id, chapters.clone()) {
+                    log::error!("could not store chapters: {}", e);
+                }
+                chapters
+            }
+            Err(e) => {
+                log::warn!("could not read chapters from {:?}: {}", path, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// bytes per second of audio, measured while playing. Until then it
+    /// follows from the size and duration of the episode if known.
+    fn bitrate(&self, total: Option<u64>) -> f32 {
+        self.stream_bitrate
+            .get()
+            .or_else(|| Some(total? as f32 / self.current.duration()?))
+            .unwrap_or(buffer::DEFAULT_BITRATE)
+    }
+
+    /// starts the stream once enough is buffered, pauses when the buffer
+    /// runs out and continues once it is filled again
+    pub fn buffer_tick(&mut self) {
+        let shared = match &self.stream {
+            Some(shared) => shared.clone(),
+            None => return,
+        };
+        let pos = self.pos();
+        let header_fetched;
+        let level = {
+            let mut cache = shared.lock();
+            let header = buffer::HEADER.min(cache.total.unwrap_or(u64::MAX));
+            header_fetched = cache.available_from(0) >= header;
+            if self.sink.is_none() && self.stream_start > 0. && header_fetched {
+                // nothing reads yet, buffer from about where playback
+                // resumes instead of from the start
+                let estimate = (self.stream_start * self.bitrate(cache.total)) as u64;
+                let estimate = cache.total.map_or(estimate, |t| estimate.min(t));
+                if cache.wanted != estimate {
+                    cache.wanted = estimate;
+                    shared.seeked.notify_one();
+                }
+            }
+            self.stream_bitrate.sample(cache.wanted, pos);
+            buffer::Level {
+                ahead: cache.available_from(cache.wanted),
+                bitrate: self.bitrate(cache.total),
+                throughput: cache.throughput.get(),
+                done: cache.fetched_to_end(),
+            }
+        };
 
-        sink_empty && downloaded_enough
+        let sink = match self.sink.as_mut() {
+            Some(sink) => sink,
+            // creating the decoder blocks until it has the header
+            None if header_fetched && level.enough(buffer::START) => {
+                return self.start_stream(shared)
+            }
+            None => return,
+        };
+        if self.buffering && level.enough(buffer::RESUME) {
+            log::info!("buffered {:.0}s of stream, continuing", level.secs());
+            sink.play();
+            self.buffering = false;
+        } else if !self.buffering && !sink.is_paused() && level.underrun() {
+            log::warn!("stream buffer ran out, pausing to buffer");
+            sink.pause();
+            self.buffering = true;
+        }
     }
 
     pub fn skip(&mut self, dur: f32) {
@@ -193,59 +493,166 @@ impl Player {
         let target = f32::max(pos + dur, 0f32);
         let target = match &self.current {
             Track::None => return,
-            // can not seek further then what was downloaded
-            // because of varying compression throughout the stream we
-            // keep a safety bound of 10 percent. TODO FIXME make sure
-            // the visualisation does not show beyond the safety bound
-            Track::Stream(info, dl_pos_percent, _) => {
-                if *dl_pos_percent <= 100. {
-                    let dl_pos_secs = dl_pos_percent * (info.duration) / 100.;
-                    let dl_pos_secs = dl_pos_secs * 0.9;
-                    f32::min(target, dl_pos_secs)
-                } else {
-                    target
-                }
-            }
-            // can not seek beyond the length of the audio file
-            Track::File(info, _) => f32::min(target, info.duration),
+            // can not seek beyond the length of the audio, parts of a
+            // stream that were not fetched are fetched when needed
+            Track::Stream(info, ..) | Track::File(info, _) => match info.duration {
+                Some(duration) => f32::min(target, duration),
+                None => target,
+            },
         };
-        self.offset += target - pos;
-        self.sink.as_mut().unwrap().set_pos(target);
+        match self.sink.as_mut() {
+            Some(sink) => sink.set_pos(target),
+            None => return, // stream did not start yet
+        }
+        // the audio thread applies the seek later, do not wait for it
+        self.position.set(target);
+        self.seeks += 1;
+        self.stream_bitrate.skip_sample();
+    }
+
+    pub fn next_chapter(&mut self) {
+        let pos = self.pos();
+        if let Some(start) = chapters::next_start(&self.chapters, pos) {
+            self.skip(start - pos);
+        }
+    }
+
+    /// to the start of the current chapter, or the one before if the
+    /// current chapter just started
+    pub fn previous_chapter(&mut self) {
+        let pos = self.pos();
+        if let Some(start) = chapters::previous_start(&self.chapters, pos) {
+            self.skip(start - pos);
+        }
     }
 
     pub fn play_pause(&mut self) -> Command<crate::Message> {
-        if let Some(elapsed) = self.last_started.take().map(|t| t.elapsed()) {
-            self.offset += elapsed.as_secs_f32();
-            self.sink.as_mut().unwrap().pause();
-        } else {
-            self.last_started = Some(Instant::now());
-            self.sink.as_mut().unwrap().play();
+        if self.buffering {
+            // the user paused while buffering, stay paused afterwards
+            self.buffering = false;
+            return Command::none();
+        }
+        if let Some(sink) = self.sink.as_mut() {
+            if sink.is_paused() {
+                sink.play();
+            } else {
+                sink.pause();
+            }
         }
         Command::none()
     }
 
     pub fn view(&mut self) -> Column<Message> {
         let column = Column::new();
+        let end_action = self.settings.end_action();
+        let sleep = self.sleep_view();
+        let has_chapters = !self.chapters.is_empty();
+        let chapter = Self::view_chapter(&self.chapters, self.current.duration(), self.pos());
         match &self.current {
             Track::None => column,
-            Track::Stream(info, download, _) => {
+            Track::Stream(info, download, ..) => {
                 let download_progress_bar = iced::ProgressBar::new(0.0..=100.0, *download);
-                let playback_bar = iced::ProgressBar::new(0.0..=info.duration, self.pos());
-                let controls = Self::view_controls(&mut self.controls, info);
+                let playback_bar = Self::view_playback(info.duration, self.pos());
+                let controls = Self::view_controls(
+                    &mut self.controls,
+                    info,
+                    self.speed.get(),
+                    end_action,
+                    has_chapters,
+                );
+                let sleep = Self::view_sleep(&mut self.controls, sleep);
+                let waiting = self.buffering || self.sink.is_none();
+                let column = if waiting && self.stream.is_some() {
+                    column.push(Text::new("buffering"))
+                } else {
+                    column
+                };
                 column
+                    .push(sleep)
                     .push(download_progress_bar)
                     .push(playback_bar)
+                    .push(chapter)
                     .push(controls)
             }
             Track::File(info, _) => {
-                let playback_bar = iced::ProgressBar::new(0.0..=info.duration, self.pos());
-                let controls = Self::view_controls(&mut self.controls, info);
-                column.push(playback_bar).push(controls)
+                let playback_bar = Self::view_playback(info.duration, self.pos());
+                let controls = Self::view_controls(
+                    &mut self.controls,
+                    info,
+                    self.speed.get(),
+                    end_action,
+                    has_chapters,
+                );
+                let sleep = Self::view_sleep(&mut self.controls, sleep);
+                column
+                    .push(sleep)
+                    .push(playback_bar)
+                    .push(chapter)
+                    .push(controls)
             }
         }
     }
 
-    fn view_controls<'a>(controls: &'a mut Controls, status: &'a TrackInfo) -> Row<'a, Message> {
+    /// progress through the episode, nothing if the duration is unknown
+    fn view_playback<'a>(duration: Option<f32>, pos: f32) -> Column<'a, Message> {
+        match duration {
+            Some(duration) => Column::new().push(iced::ProgressBar::new(0.0..=duration, pos)),
+            None => Column::new(),
+        }
+    }
+
+    /// chapter markers under the playback bar and the current chapter
+    fn view_chapter<'a>(
+        chapters: &[database::Chapter],
+        duration: Option<f32>,
+        pos: f32,
+    ) -> Column<'a, Message> {
+        let duration = match duration {
+            Some(duration) if !chapters.is_empty() => duration,
+            _ => return Column::new(),
+        };
+        let title = chapters::current(chapters, pos)
+            .map(|c| c.title.as_str())
+            .unwrap_or_default();
+        Column::new()
+            .push(chapters::markers(chapters, duration))
+            .push(Text::new(title))
+    }
+
+    /// label of the sleep timer button and if the timer is running
+    fn sleep_view(&self) -> (String, bool) {
+        let now = Instant::now();
+        let label = self.sleep.label(now, self.episode_left());
+        (label, self.sleep != SleepTimer::Off)
+    }
+
+    fn view_sleep(controls: &mut Controls, (label, running): (String, bool)) -> Row<Message> {
+        let mut row = Row::new().push(
+            Button::new(&mut controls.sleep, Text::new(label))
+                .on_press(Message::CycleSleepTimer)
+                .width(Length::FillPortion(1)),
+        );
+        if running {
+            row = row.push(
+                Button::new(&mut controls.extend_sleep, Text::new("+5 min"))
+                    .on_press(Message::ExtendSleepTimer)
+                    .width(Length::FillPortion(1)),
+            );
+        }
+        row
+    }
+
+    fn view_controls<'a>(
+        controls: &'a mut Controls,
+        status: &'a TrackInfo,
+        speed: f32,
+        end_action: database::EndAction,
+        has_chapters: bool,
+    ) -> Row<'a, Message> {
+        let end_action = match end_action {
+            database::EndAction::Stop => "then stop",
+            database::EndAction::PlayNext => "then next",
+        };
         let (button_text, button_action) = if status.paused {
             (Text::new("Pause"), Message::PlayPause)
         } else {
@@ -256,10 +663,24 @@ impl Player {
             play_pauze,
             skip_forward,
             skip_backward,
+            speed: speed_button,
+            end_action: end_action_button,
             skip_dur,
+            previous_chapter,
+            next_chapter,
+            ..
         } = controls;
-        Row::new()
-            .push(Space::with_width(Length::FillPortion(2)))
+        let row = Row::new()
+            .push(
+                Button::new(end_action_button, Text::new(end_action))
+                    .on_press(Message::CycleEndAction)
+                    .width(Length::FillPortion(1)),
+            )
+            .push(
+                Button::new(speed_button, Text::new(format!("{}x", speed)))
+                    .on_press(Message::CycleSpeed)
+                    .width(Length::FillPortion(1)),
+            )
             .push(
                 Button::new(play_pauze, button_text)
                     .on_press(button_action)
@@ -274,11 +695,26 @@ impl Player {
                 Button::new(skip_backward, Text::new("bck"))
                     .on_press(Message::Skip(-1f32 * (*skip_dur)))
                     .width(Length::FillPortion(1)),
-            )
+            );
+        if !has_chapters {
+            return row;
+        }
+        row.push(
+            Button::new(previous_chapter, Text::new("|<"))
+                .on_press(Message::PreviousChapter)
+                .width(Length::FillPortion(1)),
+        )
+        .push(
+            Button::new(next_chapter, Text::new(">|"))
+                .on_press(Message::NextChapter)
+                .width(Length::FillPortion(1)),
+        )
     }
 }
 
-pub fn handle_media_keys() -> iced::Subscription<Message> {
+/// media keys are handled directly, other keys are passed on to
+/// be looked up in the shortcuts
+pub fn handle_keys() -> iced::Subscription<Message> {
     use iced::keyboard::{self, KeyCode};
     use iced_native::event::{Event, Status};
     use iced_native::subscription::events_with;
@@ -290,13 +726,13 @@ pub fn handle_media_keys() -> iced::Subscription<Message> {
 
         if let Event::Keyboard(keyboard::Event::KeyPressed { key_code, .. }) = event {
             match key_code {
-                KeyCode::Pause => todo!(),
+                KeyCode::Pause => Some(Message::Pause),
                 KeyCode::PlayPause => Some(Message::PlayPause),
-                KeyCode::MediaStop => todo!(),
-                KeyCode::Stop => todo!(),
-                KeyCode::NextTrack => todo!(),
-                KeyCode::PrevTrack => todo!(),
-                _ => None,
+                KeyCode::MediaStop => Some(Message::Stop),
+                KeyCode::Stop => Some(Message::Stop),
+                KeyCode::NextTrack => Some(Message::Next),
+                KeyCode::PrevTrack => Some(Message::Previous),
+                key_code => Some(Message::KeyPressed(key_name(key_code))),
             }
         } else {
             None
@@ -304,56 +740,28 @@ pub fn handle_media_keys() -> iced::Subscription<Message> {
     })
 }
 
+/// name of the key as stored in the shortcut settings
+pub fn key_name(key_code: iced::keyboard::KeyCode) -> String {
+    format!("{:?}", key_code)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::io::{Read, Seek, SeekFrom};
-
-    #[test]
-    fn test_readable_reciever_seek_read_exact() {
-        let (tx, rx) = mpsc::channel();
-        let mut readable_rx = ReadableReciever::new(rx);
-        const T1: &str = "Hello world!";
-        const T2: &str = " are you getting this";
-
-        let t1 = Bytes::from(T1);
-        let t2 = Bytes::from(T2);
-        tx.send(t1).unwrap();
-
-        let mut buffer = vec![0; T1.len()];
-        readable_rx.read_exact(&mut buffer).unwrap();
-        assert_eq!(T1.as_bytes(), buffer);
-
-        tx.send(t2).unwrap();
-        readable_rx.seek(SeekFrom::Start(0)).unwrap();
-        let mut buffer = vec![0; T1.len() + T2.len()];
-        readable_rx.read_exact(&mut buffer).unwrap();
-        assert_eq!([T1, T2].concat().as_bytes(), buffer);
-    }
 
     #[test]
-    fn test_readable_reciever_seek_read_string_eof() {
-        use std::thread;
-
-        let (tx, rx) = mpsc::channel();
-        let mut readable_rx = ReadableReciever::new(rx);
-        const T1: &str = "Hello world!";
-        const T2: &str = " are you getting this";
-
-        let t1 = Bytes::from(T1);
-        tx.send(t1).unwrap();
-
-        let child = thread::spawn(move || {
-            let mut buffer = String::new();
-            readable_rx.read_to_string(&mut buffer).unwrap();
-            assert_eq!([T1, T2].concat(), buffer);
-        });
-
-        let t2 = Bytes::from(T2);
-        tx.send(t2).unwrap();
-
-        drop(tx); // indicates the end (EOF)
-                  // only now the child thread can read to end of file
-        child.join().unwrap();
+    fn speed_steps_stop_at_limits() {
+        let mut speed = 1.0;
+        for _ in 0..100 {
+            speed = faster(speed);
+        }
+        let max = speed;
+        assert_eq!(faster(max), max);
+        for _ in 0..100 {
+            speed = slower(speed);
+        }
+        assert!(speed < 1.0);
+        assert_eq!(slower(speed), speed);
+        assert_eq!(slower(faster(1.0)), 1.0);
     }
 }
@@ -0,0 +1,137 @@
+use rodio::source::SourceExt;
+use rodio::Source;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// position in the episode in seconds, written by the audio thread
+#[derive(Debug, Clone)]
+pub struct Position(Arc<AtomicU64>);
+
+impl Position {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicU64::new(0f64.to_bits())))
+    }
+    pub fn get(&self) -> f32 {
+        f64::from_bits(self.0.load(Ordering::Relaxed)) as f32
+    }
+    pub fn set(&self, seconds: f32) {
+        self.0.store((seconds as f64).to_bits(), Ordering::Relaxed)
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tracks the position from the number of samples taken from the
+/// decoder. Unlike the wall clock this does not run on while the
+/// stream underruns or the decoder stalls and is independent of the
+/// playback speed.
+pub struct Counter<S> {
+    inner: S,
+    position: Position,
+    /// position at the last update
+    seconds: f64,
+    /// samples taken since the last update
+    samples: u32,
+}
+
+/// the shared position is updated every this many samples
+const UPDATE_EVERY: u32 = 1024;
+
+impl<S> Counter<S>
+where
+    S: Source,
+    S::Item: rodio::Sample,
+{
+    pub fn new(inner: S, position: Position) -> Self {
+        let seconds = position.get() as f64;
+        Self {
+            inner,
+            position,
+            seconds,
+            samples: 0,
+        }
+    }
+
+    fn update(&mut self) {
+        let per_second = self.inner.sample_rate() as f64 * self.inner.channels() as f64;
+        self.seconds += self.samples as f64 / per_second;
+        self.samples = 0;
+        self.position.set(self.seconds as f32);
+    }
+}
+
+impl<S> Iterator for Counter<S>
+where
+    S: Source,
+    S::Item: rodio::Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.inner.next();
+        if sample.is_some() {
+            self.samples += 1;
+            if self.samples >= UPDATE_EVERY {
+                self.update();
+            }
+        } else if self.samples > 0 {
+            self.update();
+        }
+        sample
+    }
+}
+
+impl<S> Source for Counter<S>
+where
+    S: Source,
+    S::Item: rodio::Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+impl<S> SourceExt for Counter<S>
+where
+    S: SourceExt + Source,
+    S::Item: rodio::Sample,
+{
+    fn set_pos(&mut self, pos: f32) {
+        self.inner.set_pos(pos);
+        self.seconds = pos as f64;
+        self.samples = 0;
+        self.position.set(pos);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn counts_content_time() {
+        // two seconds of stereo audio
+        let source = SamplesBuffer::new(2, 8000, vec![0i16; 2 * 2 * 8000]);
+        let position = Position::new();
+        let mut counter = Counter::new(source, position.clone());
+        counter.by_ref().take(2 * 8000).for_each(drop);
+        assert!((position.get() - 1.0).abs() < 0.1);
+        counter.for_each(drop);
+        assert!((position.get() - 2.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+/// the volume goes down over this period before playback is paused
+pub const FADE: Duration = Duration::from_secs(30);
+/// time added by the extend button
+pub const EXTEND: Duration = Duration::from_secs(5 * 60);
+/// timer durations to cycle through, in minutes
+const MINUTES: [u64; 4] = [15, 30, 45, 60];
+
+/// pauses playback after a while, the last seconds are faded out
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SleepTimer {
+    #[default]
+    Off,
+    At(Instant),
+    /// stop once the current episode ends instead of playing the next
+    EndOfEpisode,
+}
+
+impl SleepTimer {
+    /// the next timer setting, from off through the durations to the
+    /// end of the episode and back to off
+    pub fn next(self, now: Instant) -> Self {
+        let minutes = match self {
+            SleepTimer::Off => return SleepTimer::after(now, MINUTES[0]),
+            SleepTimer::EndOfEpisode => return SleepTimer::Off,
+            SleepTimer::At(deadline) => {
+                // round up, the time left drops below the duration
+                // picked as soon as the timer runs
+                let left = deadline.saturating_duration_since(now);
+                (left.as_secs_f64() / 60.).ceil() as u64
+            }
+        };
+        match MINUTES.iter().find(|m| **m > minutes) {
+            Some(m) => SleepTimer::after(now, *m),
+            None => SleepTimer::EndOfEpisode,
+        }
+    }
+
+    fn after(now: Instant, minutes: u64) -> Self {
+        SleepTimer::At(now + Duration::from_secs(minutes * 60))
+    }
+
+    /// push the deadline back, for the end of episode timer the playtime
+    /// left in the episode is extended. The episode left is unknown if
+    /// its duration is.
+    pub fn extend(self, now: Instant, episode_left: Option<Duration>) -> Self {
+        match self {
+            SleepTimer::Off => SleepTimer::At(now + EXTEND),
+            SleepTimer::At(deadline) => SleepTimer::At(deadline.max(now) + EXTEND),
+            SleepTimer::EndOfEpisode => {
+                SleepTimer::At(now + episode_left.unwrap_or_default() + EXTEND)
+            }
+        }
+    }
+
+    /// time until the timer goes off, for the end of episode timer
+    /// that is the playtime left in the episode
+    pub fn remaining(&self, now: Instant, episode_left: Option<Duration>) -> Option<Duration> {
+        match self {
+            SleepTimer::Off => None,
+            SleepTimer::At(deadline) => Some(deadline.saturating_duration_since(now)),
+            SleepTimer::EndOfEpisode => episode_left,
+        }
+    }
+
+    /// volume during the fade out, 1.0 until the fade starts
+    pub fn volume(&self, now: Instant, episode_left: Option<Duration>) -> f32 {
+        match self.remaining(now, episode_left) {
+            Some(left) if left < FADE => left.as_secs_f32() / FADE.as_secs_f32(),
+            _ => 1.0,
+        }
+    }
+
+    /// if playback should pause now, the end of episode timer never
+    /// expires as the end of the episode stops playback
+    pub fn expired(&self, now: Instant) -> bool {
+        match self {
+            SleepTimer::At(deadline) => *deadline <= now,
+            _ => false,
+        }
+    }
+
+    pub fn label(&self, now: Instant, episode_left: Option<Duration>) -> String {
+        match self {
+            SleepTimer::Off => "sleep off".to_owned(),
+            SleepTimer::EndOfEpisode => "sleep at end".to_owned(),
+            SleepTimer::At(_) => {
+                let left = self.remaining(now, episode_left).unwrap().as_secs();
+                format!("sleep {}:{:02}", left / 60, left % 60)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fades_out_before_deadline() {
+        let now = Instant::now();
+        let left = Some(Duration::from_secs(3600));
+        let timer = SleepTimer::At(now + Duration::from_secs(60));
+        assert_eq!(timer.volume(now, left), 1.0);
+        let halfway = now + Duration::from_secs(45);
+        assert!((timer.volume(halfway, left) - 0.5).abs() < 1e-3);
+        assert!(!timer.expired(halfway));
+        assert!(timer.expired(now + Duration::from_secs(60)));
+
+        // the end of episode timer fades over the last of the episode
+        let at_end = SleepTimer::EndOfEpisode;
+        let left = Some(Duration::from_secs(15));
+        assert!((at_end.volume(now, left) - 0.5).abs() < 1e-3);
+        assert!(!at_end.expired(now));
+        // without a duration the end is unknown, do not fade
+        assert_eq!(at_end.volume(now, None), 1.0);
+    }
+
+    #[test]
+    fn cycle_and_extend() {
+        let now = Instant::now();
+        let left = Duration::from_secs(600);
+        let mut timer = SleepTimer::Off;
+        for minutes in &MINUTES {
+            timer = timer.next(now);
+            let remaining = timer.remaining(now, Some(left)).unwrap();
+            assert_eq!(remaining, Duration::from_secs(minutes * 60));
+        }
+        assert_eq!(timer.next(now), SleepTimer::EndOfEpisode);
+        assert_eq!(SleepTimer::EndOfEpisode.next(now), SleepTimer::Off);
+
+        // the timer runs between clicks
+        let mut now = now;
+        let mut timer = SleepTimer::Off;
+        for minutes in &MINUTES {
+            timer = timer.next(now);
+            now += Duration::from_secs(5);
+            let remaining = timer.remaining(now, Some(left)).unwrap();
+            assert_eq!(remaining, Duration::from_secs(minutes * 60 - 5));
+        }
+        assert_eq!(timer.next(now), SleepTimer::EndOfEpisode);
+
+        let extended = SleepTimer::EndOfEpisode.extend(now, Some(left));
+        assert_eq!(extended.remaining(now, Some(left)), Some(left + EXTEND));
+        // an expired timer is extended from now
+        let expired = SleepTimer::At(now - Duration::from_secs(10));
+        assert_eq!(
+            expired.extend(now, Some(left)).remaining(now, Some(left)),
+            Some(EXTEND)
+        );
+    }
+}
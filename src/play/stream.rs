@@ -1,101 +1,302 @@
-use std::io::{self, ErrorKind, Read, SeekFrom};
-use std::sync::mpsc;
-
-pub struct ReadableReciever {
-    rx: mpsc::Receiver<bytes::Bytes>,
-    /// buffer of all received data, used for seeking
-    buffer: Vec<u8>,
-    offset: usize,
+use super::buffer::Throughput;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Parts of the episode fetched so far as start and end offsets. Ranges
+/// never overlap and touching ranges are merged.
+#[derive(Debug, Default)]
+pub struct Ranges {
+    map: BTreeMap<u64, u64>,
 }
 
-impl ReadableReciever {
-    pub fn new(rx: mpsc::Receiver<bytes::Bytes>) -> Self {
-        Self {
-            rx,
-            buffer: Vec::new(),
-            offset: 0,
+impl Ranges {
+    /// end of the fetched range containing pos, pos itself if the
+    /// byte at pos was not fetched
+    pub fn cached_until(&self, pos: u64) -> u64 {
+        match self.map.range(..=pos).next_back() {
+            Some((_, end)) if *end > pos => *end,
+            _ => pos,
         }
     }
-}
 
-impl Read for ReadableReciever {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
-        let needed = buf.len();
-        let mut unread_buffer = self.buffer.len() - self.offset;
-        if needed <= unread_buffer {
-            // fill buf from buffer
-            buf.clone_from_slice(&self.buffer[self.offset..self.offset + needed]);
-            self.offset += needed;
-            return Ok(needed);
+    /// total number of bytes fetched
+    pub fn fetched(&self) -> u64 {
+        self.map.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// mark len bytes starting at pos as fetched
+    pub fn insert(&mut self, pos: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let mut start = pos;
+        let mut end = pos + len;
+        // a range ending at or after start absorbs the new one
+        if let Some((s, e)) = self.map.range(..=start).next_back() {
+            if *e >= start {
+                start = *s;
+                end = end.max(*e);
+            }
         }
+        let absorbed: Vec<_> = self.map.range(start..=end).map(|(s, e)| (*s, *e)).collect();
+        for (s, e) in absorbed {
+            self.map.remove(&s);
+            end = end.max(e);
+        }
+        self.map.insert(start, end);
+    }
+}
 
-        // get extra bytes, and put them in the buffer
-        // if no bytes are gotten this or the next call to read
-        // will return 0 indicating end of file
-        let res = self.rx.recv();
-        if res.is_err() {
-            buf[..unread_buffer]
-                .clone_from_slice(&self.buffer[self.offset..self.offset + unread_buffer]);
-            self.offset += unread_buffer;
-            return Ok(unread_buffer);
+/// The fetched parts of the episode, the data is kept in a file so
+/// memory use does not grow with the length of the episode
+#[derive(Debug)]
+pub struct Cache {
+    file: File,
+    pub ranges: Ranges,
+    /// length of the episode in bytes if the server told us
+    pub total: Option<u64>,
+    /// where the decoder reads or seeked to, the fetcher follows it
+    pub wanted: u64,
+    /// no more data will arrive, the fetcher stopped
+    pub closed: bool,
+    pub throughput: Throughput,
+}
+
+impl Cache {
+    /// everything is fetched
+    pub fn complete(&self) -> bool {
+        matches!(self.total, Some(total) if self.ranges.fetched() >= total)
+    }
+    /// bytes available without waiting starting at pos
+    pub fn available_from(&self, pos: u64) -> u64 {
+        self.ranges.cached_until(pos) - pos
+    }
+    /// everything from where the reader is to the end is fetched
+    pub fn fetched_to_end(&self) -> bool {
+        matches!(self.total, Some(total) if self.ranges.cached_until(self.wanted) >= total)
+    }
+    /// copies as many contiguous bytes as are available from pos
+    fn read(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.available_from(pos) as usize);
+        if n == 0 {
+            return Ok(0);
         }
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.read_exact(&mut buf[..n])?;
+        Ok(n)
+    }
+    fn write(&mut self, pos: u64, data: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.write_all(data)?;
+        self.ranges.insert(pos, data.len() as u64);
+        self.throughput.add(data.len() as u64, Instant::now());
+        Ok(())
+    }
+}
 
-        let bytes = res.unwrap();
-        self.buffer.extend_from_slice(&bytes);
-        unread_buffer += bytes.len();
-
-        let read = if needed <= unread_buffer {
-            // got what we needed
-            buf.clone_from_slice(&self.buffer[self.offset..self.offset + needed]);
-            needed
-        } else {
-            // less bytes then needed, return what we got do not block
-            buf[..unread_buffer]
-                .clone_from_slice(&self.buffer[self.offset..self.offset + unread_buffer]);
-            unread_buffer
+/// Stream data shared between the fetcher running in the background
+/// and the reader on the audio thread. The reader blocks until the
+/// data it needs arrived, the fetcher is woken up when the reader
+/// needs data the fetcher is not going to get on its own.
+#[derive(Debug)]
+pub struct Shared {
+    cache: Mutex<Cache>,
+    /// signaled when data arrived or the fetcher stopped
+    arrived: Condvar,
+    /// signaled when the reader seeked
+    pub seeked: tokio::sync::Notify,
+}
+
+impl Shared {
+    /// the file must be opened for reading and writing
+    pub fn new(file: File, total: Option<u64>) -> Self {
+        let cache = Cache {
+            file,
+            ranges: Ranges::default(),
+            total,
+            wanted: 0,
+            closed: false,
+            throughput: Throughput::default(),
         };
-        self.offset += read;
-        Ok(read)
+        Self {
+            cache: Mutex::new(cache),
+            arrived: Condvar::new(),
+            seeked: tokio::sync::Notify::new(),
+        }
+    }
+    pub fn lock(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap()
+    }
+    pub fn insert(&self, pos: u64, data: &[u8]) -> io::Result<()> {
+        self.lock().write(pos, data)?;
+        self.arrived.notify_all();
+        Ok(())
+    }
+    /// the fetcher stopped, readers waiting for data get end of file
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.arrived.notify_all();
     }
 }
 
-impl std::io::Seek for ReadableReciever {
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        use SeekFrom::*;
-        match pos {
-            Current(p) if self.offset as i64 + p > self.buffer.len() as i64 => Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "cannot seek after end of reader",
-            )),
-            Current(p) if self.offset as i64 + p < 0 => Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "cannot seek before start of reader",
-            )),
-            Start(p) if p > self.buffer.len() as u64 => Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "cannot seek after end of reader",
-            )),
-            End(p) if self.buffer.len() as i64 + p < 0 => Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "cannot seek before start of reader",
-            )),
-            End(p) if p > 0 => Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                "cannot seek after end of reader",
-            )),
-
-            Start(p) => {
-                self.offset = p as usize;
-                Ok(self.offset as u64)
+/// reads the stream as if it were a file, seeking to a part that was not
+/// fetched yet makes the fetcher continue from there
+pub struct StreamReader {
+    shared: Arc<Shared>,
+    offset: u64,
+}
+
+impl StreamReader {
+    pub fn new(shared: Arc<Shared>) -> Self {
+        Self { shared, offset: 0 }
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut cache = self.shared.lock();
+        loop {
+            let read = cache.read(self.offset, buf)?;
+            if read > 0 {
+                self.offset += read as u64;
+                cache.wanted = self.offset;
+                return Ok(read);
             }
-            Current(p) => {
-                self.offset = (self.offset as i64 + p) as usize;
-                Ok(self.offset as u64)
+            let at_end = matches!(cache.total, Some(total) if self.offset >= total);
+            if at_end || cache.closed {
+                return Ok(0);
             }
-            End(p) => {
-                self.offset = (self.offset as i64 + p) as usize;
-                Ok(self.offset as u64)
+            if cache.wanted != self.offset {
+                cache.wanted = self.offset;
+                self.shared.seeked.notify_one();
             }
+            // time out now and then in case the fetcher is gone without
+            // closing the stream
+            let (guard, _) = self
+                .shared
+                .arrived
+                .wait_timeout(cache, Duration::from_secs(1))
+                .unwrap();
+            cache = guard;
         }
     }
 }
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut cache = self.shared.lock();
+        let target = match pos {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::Current(p) => self.offset as i64 + p,
+            SeekFrom::End(p) => match cache.total {
+                Some(total) => total as i64 + p,
+                None => {
+                    return Err(io::Error::new(
+                        ErrorKind::Unsupported,
+                        "can not seek from the end, length of stream is unknown",
+                    ))
+                }
+            },
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "cannot seek before start of reader",
+            ));
+        }
+        self.offset = target as u64;
+        // start fetching right away if needed
+        if cache.available_from(self.offset) == 0 && cache.wanted != self.offset {
+            cache.wanted = self.offset;
+            self.shared.seeked.notify_one();
+        }
+        Ok(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    /// a file that is removed once the test is done with it
+    fn temp_file() -> File {
+        let path = std::env::temp_dir().join(format!("pods_stream_{}", rand::random::<u64>()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn ranges_merge_and_skip_overlap() {
+        let mut ranges = Ranges::default();
+        ranges.insert(0, 5);
+        ranges.insert(10, 5);
+        assert_eq!(ranges.cached_until(2), 5);
+        assert_eq!(ranges.cached_until(7), 7);
+        assert_eq!(ranges.fetched(), 10);
+
+        // fills the gap and overlaps both sides
+        ranges.insert(3, 11);
+        assert_eq!(ranges.cached_until(0), 15);
+        assert_eq!(ranges.fetched(), 15);
+        ranges.insert(20, 2);
+        ranges.insert(15, 5);
+        assert_eq!(ranges.cached_until(0), 22);
+    }
+
+    #[test]
+    fn reader_waits_for_data_and_seeks_anywhere() {
+        const T1: &str = "Hello world!";
+        const T2: &str = " are you getting this";
+        let total = (T1.len() + T2.len()) as u64;
+        let shared = Arc::new(Shared::new(temp_file(), Some(total)));
+        shared.insert(0, T1.as_bytes()).unwrap();
+
+        let mut reader = StreamReader::new(shared.clone());
+        let mut buffer = vec![0; T1.len()];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(T1.as_bytes(), buffer);
+
+        // seeking past what was fetched is fine, the read then waits
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        assert_eq!(shared.lock().wanted, total - 4);
+        let fetcher = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.insert(T1.len() as u64, T2.as_bytes()).unwrap())
+        };
+        let mut end = String::new();
+        reader.read_to_string(&mut end).unwrap();
+        assert_eq!(end, "this");
+        fetcher.join().unwrap();
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = String::new();
+        reader.read_to_string(&mut all).unwrap();
+        assert_eq!([T1, T2].concat(), all);
+        assert!(shared.lock().complete());
+    }
+
+    #[test]
+    fn closed_stream_ends_reads() {
+        let shared = Arc::new(Shared::new(temp_file(), None));
+        shared.insert(0, b"partial").unwrap();
+        let mut reader = StreamReader::new(shared.clone());
+        let closer = thread::spawn(move || shared.close());
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"partial");
+        closer.join().unwrap();
+    }
+}
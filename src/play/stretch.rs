@@ -0,0 +1,290 @@
+use rodio::source::SourceExt;
+use rodio::Source;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// playback speed shared between the player and the audio thread
+#[derive(Debug, Clone)]
+pub struct Speed(Arc<AtomicU32>);
+
+impl Speed {
+    pub fn new(speed: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(speed.to_bits())))
+    }
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set(&self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.0.store(speed.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// Changes the playback speed without changing the pitch using waveform
+/// similarity overlap-add (WSOLA). The input is cut into overlapping
+/// segments that are taken further apart (speeding up) or closer together
+/// (slowing down) than they are placed in the output. Each segment start
+/// is moved a little to where the waveform best continues the previous
+/// segment, which prevents the phasing artifacts of plain overlap-add.
+pub struct Stretch<S> {
+    inner: S,
+    speed: Speed,
+    channels: usize,
+    /// frames two segments overlap, a segment is twice this long
+    overlap: usize,
+    /// frames a segment start may move to find a similar waveform
+    tolerance: usize,
+    /// hann window over a whole segment
+    window: Vec<f32>,
+    /// interleaved input, the first sample belongs to frame `input_start`
+    input: VecDeque<f32>,
+    input_start: usize,
+    exhausted: bool,
+    /// frame the previous segment started on, none before the first
+    prev: Option<usize>,
+    /// frame the next segment starts on before it is moved
+    nominal: f64,
+    /// windowed second half of the previous segment
+    tail: Vec<f32>,
+    ready: VecDeque<i16>,
+}
+
+impl<S> Stretch<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(inner: S, speed: Speed) -> Self {
+        let channels = inner.channels() as usize;
+        let rate = inner.sample_rate() as usize;
+        let overlap = rate / 50; // 20ms
+        let tolerance = rate / 100; // 10ms
+        let len = 2 * overlap;
+        let window = (0..len)
+            .map(|i| {
+                let phase = 2. * std::f32::consts::PI * i as f32 / len as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        Self {
+            inner,
+            speed,
+            channels,
+            overlap,
+            tolerance,
+            window,
+            input: VecDeque::new(),
+            input_start: 0,
+            exhausted: false,
+            prev: None,
+            nominal: 0.,
+            tail: vec![0.; overlap * channels],
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// forget all buffered audio, needed after seeking
+    fn reset(&mut self) {
+        self.input.clear();
+        self.input_start = 0;
+        self.exhausted = false;
+        self.prev = None;
+        self.nominal = 0.;
+        self.tail = vec![0.; self.overlap * self.channels];
+        self.ready.clear();
+    }
+
+    fn input_end(&self) -> usize {
+        self.input_start + self.input.len() / self.channels
+    }
+
+    fn fill(&mut self, until_frame: usize) {
+        while !self.exhausted && self.input_end() < until_frame {
+            match self.inner.next() {
+                Some(sample) => self.input.push_back(sample as f32),
+                None => self.exhausted = true,
+            }
+        }
+    }
+
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.input[(frame - self.input_start) * self.channels + channel]
+    }
+
+    /// the channels mixed down, only used for finding similar waveforms
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels).map(|c| self.sample(frame, c)).sum()
+    }
+
+    /// normalised cross correlation over the overlap, skips every
+    /// other frame as the precision is not needed
+    fn similarity(&self, candidate: usize, natural: usize) -> f32 {
+        let mut correlation = 0.;
+        let mut energy = 0.;
+        for i in (0..self.overlap).step_by(2) {
+            let c = self.mono(candidate + i);
+            correlation += c * self.mono(natural + i);
+            energy += c * c;
+        }
+        correlation / (energy + 1.).sqrt()
+    }
+
+    /// start of the next segment, the candidate near the nominal start
+    /// that best continues the previous segment
+    fn next_start(&mut self) -> Option<usize> {
+        let segment = 2 * self.overlap;
+        let nominal = self.nominal as usize;
+        let prev = match self.prev {
+            None => return Some(nominal.max(self.input_start)),
+            Some(prev) => prev,
+        };
+        let natural = prev + self.overlap;
+        let lowest = nominal.saturating_sub(self.tolerance).max(self.input_start);
+        let highest = nominal + self.tolerance;
+        self.fill((highest + segment).max(natural + self.overlap));
+
+        let end = self.input_end();
+        if natural + self.overlap > end {
+            return None;
+        }
+        (lowest..=highest)
+            .take_while(|c| c + segment <= end)
+            .map(|c| (c, self.similarity(c, natural)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(c, _)| c)
+    }
+
+    /// overlap-adds the next segment, returns false if the input ran out
+    fn produce(&mut self) -> bool {
+        let segment = 2 * self.overlap;
+        let start = match self.next_start() {
+            Some(start) => start,
+            None => return false,
+        };
+        self.fill(start + segment);
+        if start + segment > self.input_end() {
+            return false;
+        }
+
+        for i in 0..self.overlap {
+            for c in 0..self.channels {
+                let sample = self.sample(start + i, c) * self.window[i];
+                let mixed = self.tail[i * self.channels + c] + sample;
+                let mixed = mixed.clamp(i16::MIN as f32, i16::MAX as f32);
+                self.ready.push_back(mixed as i16);
+            }
+        }
+        for i in 0..self.overlap {
+            for c in 0..self.channels {
+                let frame = self.overlap + i;
+                self.tail[i * self.channels + c] =
+                    self.sample(start + frame, c) * self.window[frame];
+            }
+        }
+
+        let hop = self.overlap as f64 * self.speed.get() as f64;
+        self.prev = Some(start);
+        self.nominal += hop;
+
+        // input before both the next natural continuation and the
+        // earliest next candidate is no longer needed
+        let keep =
+            (start + self.overlap).min((self.nominal as usize).saturating_sub(self.tolerance));
+        let drop = keep.saturating_sub(self.input_start) * self.channels;
+        self.input.drain(..drop.min(self.input.len()));
+        self.input_start = keep.max(self.input_start);
+        true
+    }
+}
+
+impl<S> Iterator for Stretch<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        // at normal speed the audio passes through untouched
+        let idle = self.prev.is_none() && self.ready.is_empty();
+        if idle && self.speed.get() == 1.0 {
+            return self.inner.next();
+        }
+        if self.ready.is_empty() && !self.produce() {
+            // the input ran out, play what is left of the last segment
+            self.ready.extend(self.tail.drain(..).map(|s| s as i16));
+            self.exhausted = true;
+        }
+        self.ready.pop_front()
+    }
+}
+
+impl<S> Source for Stretch<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl<S> SourceExt for Stretch<S>
+where
+    S: SourceExt + Source<Item = i16>,
+{
+    fn set_pos(&mut self, pos: f32) {
+        self.reset();
+        self.inner.set_pos(pos);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(seconds: f32) -> SamplesBuffer<i16> {
+        let rate = 8000;
+        let samples: Vec<i16> = (0..(seconds * rate as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                (8000. * (2. * std::f32::consts::PI * 220. * t).sin()) as i16
+            })
+            .collect();
+        SamplesBuffer::new(1, rate, samples)
+    }
+
+    #[test]
+    fn output_length_scales_with_speed() {
+        for &speed in &[0.5f32, 1.5, 2.0] {
+            let stretched = Stretch::new(sine(2.), Speed::new(speed));
+            let len = stretched.count() as f32;
+            let expected = 2. * 8000. / speed;
+            assert!(
+                (len - expected).abs() < 0.05 * expected,
+                "{} {}",
+                speed,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn normal_speed_passes_through() {
+        let stretched: Vec<_> = Stretch::new(sine(0.1), Speed::new(1.0)).collect();
+        let original: Vec<_> = sine(0.1).collect();
+        assert_eq!(stretched, original);
+    }
+}
@@ -1,21 +1,31 @@
+use super::stream::Shared;
+use crate::download::{self, FileType};
+use crate::retry;
 use iced_futures::futures;
-use std::sync::{mpsc, Arc, Mutex};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_TYPE, RANGE};
+use reqwest::{Response, StatusCode};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-pub fn play(url: String) -> iced::Subscription<Progress> {
-    iced::Subscription::from_recipe(Stream { url })
+/// stream from url caching the episode at path (see: `download::stream_path`)
+pub fn play(url: String, path: PathBuf) -> iced::Subscription<Progress> {
+    iced::Subscription::from_recipe(Stream { url, path })
 }
 
 #[derive(Debug, Clone)]
 pub enum Progress {
-    Started(Arc<Mutex<mpsc::Receiver<bytes::Bytes>>>),
+    /// also gives the type of the audio if the server told us
+    Started(Arc<Shared>, Option<FileType>),
+    /// percentage of the episode fetched
     Advanced(f32),
+    /// everything was fetched, the episode is now downloaded
     Finished,
     StreamError(String),
-    ToShortError,
 }
 
 pub struct Stream {
     url: String,
+    path: PathBuf,
 }
 
 impl<H, I> iced_futures::subscription::Recipe<H, I> for Stream
@@ -35,80 +45,248 @@ where
         _input: futures::stream::BoxStream<'static, I>,
     ) -> futures::stream::BoxStream<'static, Self::Output> {
         Box::pin(futures::stream::unfold(
-            State::Start(self.url),
+            State::Start(self.url, self.path),
             |state| async move { stream_state_machine(state).await },
         ))
     }
 }
 
+/// the reader needing data this close ahead of the response does not
+/// warrant a new request, it gets there soon enough
+const NEAR: u64 = 512 * 1024;
+
+/// Fetches the parts of the episode the reader needs. Starts at the
+/// beginning and follows the reader when it seeks using range requests.
+/// The stream is closed when this is dropped, which happens when the
+/// subscription ends because another episode started playing. An
+/// incomplete cache file is then removed.
+#[derive(Debug)]
+pub struct Fetcher {
+    shared: Arc<Shared>,
+    client: reqwest::Client,
+    url: String,
+    /// the server supports range requests
+    ranged: bool,
+    /// the cache file
+    path: PathBuf,
+    /// file type according to the server
+    content_type: Option<FileType>,
+}
+
+impl Drop for Fetcher {
+    fn drop(&mut self) {
+        self.shared.close();
+        if self.shared.lock().complete() {
+            return; // turned into a download
+        }
+        match std::fs::remove_file(&self.path) {
+            Ok(_) => log::debug!("removed stream cache: {:?}", self.path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => log::error!("could not remove stream cache {:?}: {}", self.path, e),
+        }
+    }
+}
+
+impl Fetcher {
+    fn progress(&self) -> Progress {
+        let cache = self.shared.lock();
+        let percentage = cache
+            .total
+            .map(|t| 100.0 * cache.ranges.fetched() as f32 / t as f32)
+            .unwrap_or(0.0);
+        Progress::Advanced(percentage)
+    }
+
+    /// where to continue fetching if not at pos, either because the
+    /// reader needs data elsewhere or we already have what follows
+    fn reposition(&self, pos: u64) -> Option<u64> {
+        if !self.ranged {
+            return None;
+        }
+        let cache = self.shared.lock();
+        let total = cache.total?;
+        let needed = cache.ranges.cached_until(cache.wanted);
+        if needed < total && needed != pos && (needed < pos || needed > pos + NEAR) {
+            return Some(needed);
+        }
+        let fetched_until = cache.ranges.cached_until(pos);
+        if fetched_until > pos {
+            return Some(fetched_until);
+        }
+        None
+    }
+
+    /// first byte after the reader that was not fetched, none if
+    /// everything after the reader is available
+    fn needed(&self) -> Option<u64> {
+        let cache = self.shared.lock();
+        let needed = cache.ranges.cached_until(cache.wanted);
+        match cache.total {
+            Some(total) if needed >= total => None,
+            _ => Some(needed),
+        }
+    }
+
+    fn complete(&self) -> bool {
+        self.shared.lock().complete()
+    }
+
+    /// everything was fetched, keep it as a download
+    async fn finish(self) -> Option<(Progress, State)> {
+        let total = {
+            let mut cache = self.shared.lock();
+            // the server did not tell, it is what we got
+            let fetched = cache.ranges.fetched();
+            *cache.total.get_or_insert(fetched)
+        };
+        let promoted = download::promote_stream(self.path.clone(), total, self.content_type).await;
+        match promoted {
+            Ok(()) => log::info!("stream finished, kept as download"),
+            Err(e) => log::error!("could not keep stream as download: {}", e),
+        }
+        Some((Progress::Finished, State::Finished))
+    }
+
+    async fn open(mut self, from: u64) -> Option<(Progress, State)> {
+        let total = self.shared.lock().total;
+        if matches!(total, Some(total) if from >= total) {
+            return Some((self.progress(), State::Idle(self)));
+        }
+        log::debug!("fetching stream from byte: {}", from);
+        self.shared.lock().throughput.pause();
+        let range = format!("bytes={}-", from);
+        let send = || {
+            let request = self
+                .client
+                .get(self.url.as_str())
+                .header(RANGE, range.as_str());
+            async move { request.send().await?.error_for_status() }
+        };
+        match retry::retry(&retry::Policy::default(), send).await {
+            Ok(res) if res.status() == StatusCode::PARTIAL_CONTENT => {
+                Some((self.progress(), State::Fetching(self, res, from)))
+            }
+            Ok(res) => {
+                log::warn!("server ignored range request, streaming from the start");
+                self.ranged = false;
+                Some((self.progress(), State::Fetching(self, res, 0)))
+            }
+            Err(e) => Some((Progress::StreamError(e.to_string()), State::Finished)),
+        }
+    }
+}
+
+/// an empty cache file, any left over from before is overwritten
+async fn cache_file(path: &Path) -> std::io::Result<std::fs::File> {
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+async fn start(url: String, path: PathBuf) -> Option<(Progress, State)> {
+    log::debug!("streaming url: {}", &url);
+    let client = reqwest::Client::new();
+    let send = || {
+        let request = client.get(url.as_str());
+        async move { request.send().await?.error_for_status() }
+    };
+    let res = match retry::retry(&retry::Policy::default(), send).await {
+        Ok(res) => res,
+        Err(e) => return Some((Progress::StreamError(e.to_string()), State::Finished)),
+    };
+    let total = res.content_length();
+    let ranged = total.is_some()
+        && res
+            .headers()
+            .get(ACCEPT_RANGES)
+            .map(|r| r.as_bytes() == b"bytes")
+            .unwrap_or(false);
+    if !ranged {
+        log::warn!("server does not support range requests, can only seek in what was fetched");
+    }
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .and_then(FileType::from_mime);
+    let file = match cache_file(&path).await {
+        Ok(file) => file,
+        Err(e) => return Some((Progress::StreamError(e.to_string()), State::Finished)),
+    };
+
+    log::debug!("caching stream in: {:?}", &path);
+    let shared = Arc::new(Shared::new(file, total));
+    let fetcher = Fetcher {
+        shared: shared.clone(),
+        client,
+        url,
+        ranged,
+        path,
+        content_type,
+    };
+    let started = Progress::Started(shared, content_type);
+    Some((started, State::Fetching(fetcher, res, 0)))
+}
+
 async fn stream_state_machine(current: State) -> Option<(Progress, State)> {
     match current {
-        State::Start(url) => {
-            log::debug!("streaming url: {}", &url);
-            let (tx, rx) = mpsc::channel();
-            let response = reqwest::get(&url).await;
-            if let Err(e) = response {
-                return Some((Progress::StreamError(e.to_string()), State::Finished));
+        State::Start(url, path) => start(url, path).await,
+        State::Fetching(fetcher, mut res, pos) => {
+            if let Some(from) = fetcher.reposition(pos) {
+                return fetcher.open(from).await;
             }
-            let res = response.unwrap();
-            let total = res.content_length();
-            let rx = Arc::new(Mutex::new(rx));
-            let state = DownloadData {
-                res,
-                tx,
-                total,
-                downloaded: 0,
+            let seeked = fetcher.shared.seeked.notified();
+            let chunk = tokio::select! {
+                chunk = res.chunk() => chunk,
+                // check if the reader moved away from this response
+                _ = seeked, if fetcher.ranged => {
+                    return Some((fetcher.progress(), State::Fetching(fetcher, res, pos)));
+                }
             };
-            Some((Progress::Started(rx), State::Buffering(state)))
+            match chunk {
+                Err(e) if fetcher.ranged => {
+                    log::warn!("stream interrupted, continuing: {}", e);
+                    fetcher.open(pos).await
+                }
+                Err(e) => Some((Progress::StreamError(e.to_string()), State::Finished)),
+                Ok(None) if fetcher.ranged => Some((fetcher.progress(), State::Idle(fetcher))),
+                Ok(None) => fetcher.finish().await,
+                Ok(Some(chunk)) => {
+                    if let Err(e) = fetcher.shared.insert(pos, &chunk) {
+                        return Some((Progress::StreamError(e.to_string()), State::Finished));
+                    }
+                    if fetcher.complete() {
+                        return fetcher.finish().await;
+                    }
+                    let pos = pos + chunk.len() as u64;
+                    Some((fetcher.progress(), State::Fetching(fetcher, res, pos)))
+                }
+            }
         }
-        State::Buffering(mut state) => match state.res.chunk().await {
-            Err(e) => Some((Progress::StreamError(e.to_string()), State::Finished)),
-            Ok(None) => Some((Progress::ToShortError, State::Finished)),
-            Ok(Some(chunk)) => {
-                state.downloaded += chunk.len() as u64;
-                state.tx.send(chunk).unwrap();
-                let percentage = state
-                    .total
-                    .map(|t| 100.0 * state.downloaded as f32 / t as f32)
-                    .unwrap_or(0.0);
-                let progress = Progress::Advanced(percentage);
-                Some(if state.downloaded > 4096 {
-                    (progress, State::Streaming(state))
-                } else {
-                    (progress, State::Buffering(state))
-                })
+        State::Idle(fetcher) => {
+            if let Some(from) = fetcher.needed() {
+                return fetcher.open(from).await;
             }
-        },
-        State::Streaming(mut state) => match state.res.chunk().await {
-            Err(e) => Some((Progress::StreamError(e.to_string()), State::Finished)),
-            Ok(None) => Some((Progress::Finished, State::Finished)),
-            Ok(Some(chunk)) => {
-                state.downloaded += chunk.len() as u64;
-                state.tx.send(chunk).unwrap();
-                let percentage = state
-                    .total
-                    .map(|t| 100.0 * state.downloaded as f32 / t as f32)
-                    .unwrap_or(0.0);
-                let progress = Progress::Advanced(percentage);
-                Some((progress, State::Streaming(state)))
+            if fetcher.complete() {
+                return fetcher.finish().await;
             }
-        },
+            // everything after the reader is here, wait for it to seek
+            fetcher.shared.seeked.notified().await;
+            Some((fetcher.progress(), State::Idle(fetcher)))
+        }
         State::Finished => None,
     }
 }
 
-#[derive(Debug)]
-pub struct DownloadData {
-    res: reqwest::Response,
-    tx: mpsc::Sender<bytes::Bytes>,
-    total: Option<u64>,
-    downloaded: u64,
-}
-
 #[derive(Debug)]
 pub enum State {
-    Start(String),
-    Buffering(DownloadData),
-    Streaming(DownloadData),
+    Start(String, PathBuf),
+    Fetching(Fetcher, Response, u64),
+    /// everything after the reader was fetched
+    Idle(Fetcher),
     Finished,
 }
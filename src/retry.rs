@@ -0,0 +1,96 @@
+use rand::Rng;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
+
+/// how often and how fast network operations are retried
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// attempts including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Policy {
+    /// whether another attempt may follow the given (zero based) attempt
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt + 1 < self.max_attempts
+    }
+    /// exponential backoff with jitter, the jitter spreads out retries
+    /// of downloads that failed at the same moment
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay * 2u32.saturating_pow(attempt);
+        let backoff = backoff.min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.5..1.5);
+        backoff.mul_f32(jitter)
+    }
+}
+
+/// network and server (5xx) errors are retryable, client (4xx) errors
+/// except for timeouts and rate limiting are not
+pub fn is_retryable(e: &reqwest::Error) -> bool {
+    if let Some(status) = e.status() {
+        return status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS;
+    }
+    e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+}
+
+/// run the operation until it succeeds, fails with an error that is
+/// not retryable or the policy runs out of attempts
+pub async fn retry<T, F, Fut>(policy: &Policy, mut operation: F) -> Result<T, reqwest::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, reqwest::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Ok(res) => return Ok(res),
+            Err(e) if is_retryable(&e) && policy.should_retry(attempt) => {
+                let delay = policy.delay(attempt);
+                attempt += 1;
+                log::warn!("attempt {} failed, retrying in {:?}: {}", attempt, delay, e);
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = Policy::default();
+        for attempt in 0..4 {
+            let expected = policy.base_delay * 2u32.pow(attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= expected.mul_f32(0.5));
+            assert!(delay <= expected.mul_f32(1.5));
+        }
+        assert!(policy.delay(40) <= policy.max_delay.mul_f32(1.5));
+    }
+
+    #[test]
+    fn attempts_are_limited() {
+        let policy = Policy::default();
+        assert!(policy.should_retry(0));
+        assert!(policy.should_retry(policy.max_attempts - 2));
+        assert!(!policy.should_retry(policy.max_attempts - 1));
+    }
+}